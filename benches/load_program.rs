@@ -0,0 +1,108 @@
+//! Measures `LoadProgram` from a non-zero array by running UM programs
+//! through `Machine::execute`.
+//!
+//! Each round follows the common UMIX pattern: allocate an array, copy the
+//! program's code into it, load it as the program, abandon the original,
+//! then amend the program once. Only the last two steps differ between the
+//! two programs timed. Abandoning first leaves array 0 unshared, so the
+//! amendment happens in place; amending first, while the loaded array is
+//! still live, copies the whole program, which is what every load used to
+//! cost when it cloned the array into slot 0.
+//!
+//! Run with `cargo bench --bench load_program`.
+
+use an_urgent_appeal::asm::assemble;
+use an_urgent_appeal::console::BufferConsole;
+use an_urgent_appeal::machine::{ExitReason, Machine, Word};
+use std::time::{Duration, Instant};
+
+const SIZES: [usize; 4] = [1 << 10, 1 << 14, 1 << 18, 1 << 22];
+
+fn rounds_for(size: usize) -> usize {
+    ((1 << 26) / size).min(1 << 14)
+}
+
+/// A program running `rounds` rounds on arrays of `size` platters, with
+/// `last_steps` amending array 0 and abandoning the loaded array in some
+/// order. `r2` holds an offset past the code, and `r1` the loaded array.
+fn source(size: usize, rounds: usize, last_steps: &str, code_len: Word) -> String {
+    format!(
+        "        orthography r7 <- {size}
+        orthography r5 <- {rounds}
+loop:   alloc r1 <- r7
+        orthography r2 <- 0
+copy:   index r3 <- r0[r2]
+        amend r1[r2] <- r3
+        orthography r3 <- 1
+        add r2 <- r2, r3
+        li r3 <- {minus_len}
+        add r3 <- r2, r3            ; zero once the code is copied
+        orthography r4 <- copied
+        orthography r6 <- copy
+        cmov r4 <- r6 if r3
+        loadprog r0, r4
+copied: orthography r4 <- loaded
+        loadprog r1, r4
+loaded: {last_steps}
+        li r3 <- 0xffffffff
+        add r5 <- r5, r3
+        orthography r4 <- done
+        orthography r6 <- loop
+        cmov r4 <- r6 if r5
+        loadprog r0, r4
+done:   halt
+",
+        size = size,
+        rounds = rounds,
+        minus_len = code_len.wrapping_neg(),
+        last_steps = last_steps,
+    )
+}
+
+fn program(size: usize, rounds: usize, last_steps: &str) -> Vec<u8> {
+    // The length of the code does not depend on the constant it loads.
+    let code_len = assemble(&source(size, rounds, last_steps, 1))
+        .unwrap()
+        .len() as Word;
+    assert!(code_len as usize <= size);
+    assemble(&source(size, rounds, last_steps, code_len))
+        .unwrap()
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect()
+}
+
+fn run(program: Vec<u8>) -> Duration {
+    let mut m = Machine::with_console(program, Box::new(BufferConsole::default()));
+    let start = Instant::now();
+    let reason = m.execute();
+    let elapsed = start.elapsed();
+    assert!(matches!(reason, ExitReason::Halted), "{:?}", reason);
+    elapsed
+}
+
+fn per_round(total: Duration, rounds: usize) -> f64 {
+    total.as_secs_f64() * 1e9 / rounds as f64
+}
+
+fn main() {
+    println!(
+        "{:>10} {:>8} {:>14} {:>14} {:>8}",
+        "platters", "rounds", "copied ns/op", "shared ns/op", "speedup"
+    );
+    for &size in SIZES.iter() {
+        let rounds = rounds_for(size);
+        let copied = program(size, rounds, "amend r0[r2] <- r0\n        abandon r1");
+        let shared = program(size, rounds, "abandon r1\n        amend r0[r2] <- r0");
+        let copied = per_round(run(copied), rounds);
+        let shared = per_round(run(shared), rounds);
+        println!(
+            "{:>10} {:>8} {:>14.0} {:>14.0} {:>7.2}x",
+            size,
+            rounds,
+            copied,
+            shared,
+            copied / shared
+        );
+    }
+}
@@ -0,0 +1,51 @@
+//! Static analysis of scrolls.
+
+use crate::instructions::Instruction;
+use crate::machine::Word;
+use std::collections::BTreeMap;
+
+/// Maps the finger of each `LoadProgram` whose target can be determined
+/// statically to that target. A target is known when the finger register
+/// was last set by a `LoadRegister` since the previous jump, and lies within
+/// the program.
+///
+/// The array register is not checked, so a target may belong to a program
+/// swap rather than a jump within array 0.
+pub fn jump_targets(program: &[Word]) -> BTreeMap<Word, Word> {
+    let mut targets = BTreeMap::new();
+    // Register values known to be constant since the last jump.
+    let mut known: [Option<Word>; 8] = [None; 8];
+    for (finger, &word) in program.iter().enumerate() {
+        let inst = match Instruction::decode_from(word) {
+            Ok(inst) => inst,
+            Err(_) => {
+                known = [None; 8];
+                continue;
+            }
+        };
+        match inst {
+            Instruction::LoadRegister { dest, val } => known[dest.0 as usize] = Some(val),
+            Instruction::LoadProgram { finger: target, .. } => {
+                if let Some(target) = known[target.idx as usize] {
+                    if (target as usize) < program.len() {
+                        targets.insert(finger as Word, target);
+                    }
+                }
+                known = [None; 8];
+            }
+            Instruction::Halt => known = [None; 8],
+            Instruction::ConditionalMove { dest, .. }
+            | Instruction::ArrayIndex { dest, .. }
+            | Instruction::Add { dest, .. }
+            | Instruction::Multiply { dest, .. }
+            | Instruction::Divide { dest, .. }
+            | Instruction::Nand { dest, .. }
+            | Instruction::Input { dest } => known[dest.0 as usize] = None,
+            Instruction::Allocate { result, .. } => known[result.0 as usize] = None,
+            Instruction::ArrayAmend { .. }
+            | Instruction::Abandon { .. }
+            | Instruction::Output { .. } => {}
+        }
+    }
+    targets
+}
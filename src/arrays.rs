@@ -0,0 +1,242 @@
+use crate::errors::UmError;
+use crate::instructions::ArrayId;
+use crate::machine::Word;
+use std::sync::Arc;
+
+/// Storage for every array other than the program (array 0).
+///
+/// Identifiers index directly into a slab, so lookups never hash.
+/// Abandoned identifiers go onto a free list and are handed out again by
+/// later allocations; an identifier is only ever reissued once abandoned.
+pub struct ArrayStore {
+    /// Slot `i` holds the array identified by `i + 1`.
+    slots: Vec<Option<Arc<[Word]>>>,
+    /// Identifiers of abandoned slots, reused most recent first.
+    free: Vec<Word>,
+    /// Platters in all live arrays.
+    platters: u64,
+    /// Highest identifier that may be issued.
+    max_id: Word,
+}
+
+impl Default for ArrayStore {
+    fn default() -> ArrayStore {
+        ArrayStore::from_parts(Vec::new(), Vec::new())
+    }
+}
+
+impl ArrayStore {
+    pub fn new() -> ArrayStore {
+        ArrayStore::default()
+    }
+
+    /// Stores `array` under an identifier that is not currently live.
+    pub fn allocate(&mut self, array: Arc<[Word]>) -> Result<ArrayId, UmError> {
+        let platters = array.len() as u64;
+        let id = match self.free.pop() {
+            Some(id) => {
+                self.slots[id as usize - 1] = Some(array);
+                id
+            }
+            None => {
+                if self.slots.len() >= self.max_id as usize {
+                    return Err(UmError::ArrayIdsExhausted);
+                }
+                self.slots.push(Some(array));
+                self.slots.len() as Word
+            }
+        };
+        self.platters += platters;
+        Ok(ArrayId(id))
+    }
+
+    /// Removes the array, making its identifier available again.
+    pub fn abandon(&mut self, id: ArrayId) -> Option<Arc<[Word]>> {
+        let array = self.slot_mut(id)?.take()?;
+        self.free.push(id.0);
+        self.platters -= array.len() as u64;
+        Some(array)
+    }
+
+    /// Rebuilds a store from the parts `slots` and `free` returned.
+    pub(crate) fn from_parts(slots: Vec<Option<Arc<[Word]>>>, free: Vec<Word>) -> ArrayStore {
+        let platters = slots.iter().flatten().map(|array| array.len() as u64).sum();
+        ArrayStore {
+            slots,
+            free,
+            platters,
+            max_id: Word::MAX,
+        }
+    }
+
+    /// Every slot ever used; slot `i` holds the array identified by `i + 1`.
+    pub(crate) fn slots(&self) -> &[Option<Arc<[Word]>>] {
+        &self.slots
+    }
+
+    /// Abandoned identifiers in the order they will be reused, last first.
+    pub(crate) fn free(&self) -> &[Word] {
+        &self.free
+    }
+
+    /// Number of live arrays.
+    pub fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    /// Platters in all live arrays.
+    pub fn platters(&self) -> u64 {
+        self.platters
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Live arrays in identifier order.
+    pub fn iter(&self) -> impl Iterator<Item = (ArrayId, &[Word])> {
+        self.slots
+            .iter()
+            .enumerate()
+            .filter_map(|(i, slot)| Some((ArrayId(i as Word + 1), &slot.as_ref()?[..])))
+    }
+
+    pub fn get(&self, id: ArrayId) -> Option<&Arc<[Word]>> {
+        match id.0 {
+            0 => None,
+            id => self.slots.get(id as usize - 1)?.as_ref(),
+        }
+    }
+
+    pub fn get_mut(&mut self, id: ArrayId) -> Option<&mut Arc<[Word]>> {
+        self.slot_mut(id)?.as_mut()
+    }
+
+    fn slot_mut(&mut self, id: ArrayId) -> Option<&mut Option<Arc<[Word]>>> {
+        match id.0 {
+            0 => None,
+            id => self.slots.get_mut(id as usize - 1),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn array(len: usize) -> Arc<[Word]> {
+        vec![0; len].into()
+    }
+
+    fn allocate(store: &mut ArrayStore, len: usize) -> Word {
+        store.allocate(array(len)).unwrap().0
+    }
+
+    #[test]
+    fn identifiers_start_at_one() {
+        let mut store = ArrayStore::new();
+        assert_eq!(allocate(&mut store, 1), 1);
+        assert_eq!(allocate(&mut store, 1), 2);
+        assert_eq!(allocate(&mut store, 1), 3);
+    }
+
+    #[test]
+    fn abandoned_identifiers_are_reused_last_first() {
+        let mut store = ArrayStore::new();
+        for _ in 0..4 {
+            allocate(&mut store, 1);
+        }
+        for id in [2, 4, 1] {
+            store.abandon(ArrayId(id)).unwrap();
+        }
+        assert_eq!(allocate(&mut store, 1), 1);
+        assert_eq!(allocate(&mut store, 1), 4);
+        assert_eq!(allocate(&mut store, 1), 2);
+        assert_eq!(allocate(&mut store, 1), 5);
+    }
+
+    #[test]
+    fn live_identifiers_are_never_reissued() {
+        let mut store = ArrayStore::new();
+        let mut live = Vec::new();
+        for round in 0..200 {
+            if round % 3 == 2 {
+                let id = live.remove(live.len() / 2);
+                store.abandon(ArrayId(id)).unwrap();
+            }
+            let id = allocate(&mut store, round);
+            assert!(!live.contains(&id), "{} issued twice", id);
+            live.push(id);
+        }
+        assert_eq!(store.len(), live.len());
+    }
+
+    #[test]
+    fn array_zero_is_not_stored() {
+        let mut store = ArrayStore::new();
+        allocate(&mut store, 1);
+        assert!(store.get(ArrayId(0)).is_none());
+        assert!(store.get_mut(ArrayId(0)).is_none());
+        assert!(store.abandon(ArrayId(0)).is_none());
+        assert_eq!(store.len(), 1);
+    }
+
+    #[test]
+    fn unknown_identifiers_are_not_found() {
+        let mut store = ArrayStore::new();
+        allocate(&mut store, 1);
+        allocate(&mut store, 1);
+        store.abandon(ArrayId(2)).unwrap();
+        for id in [2, 3, Word::MAX] {
+            assert!(store.get(ArrayId(id)).is_none());
+            assert!(store.get_mut(ArrayId(id)).is_none());
+            assert!(store.abandon(ArrayId(id)).is_none());
+        }
+        assert_eq!(store.free(), &[2]);
+        assert!(store.get(ArrayId(1)).is_some());
+    }
+
+    #[test]
+    fn live_arrays_and_platters_are_counted() {
+        let mut store = ArrayStore::new();
+        assert!(store.is_empty());
+        assert_eq!(store.platters(), 0);
+        allocate(&mut store, 3);
+        allocate(&mut store, 5);
+        allocate(&mut store, 0);
+        assert_eq!((store.len(), store.platters()), (3, 8));
+        store.abandon(ArrayId(1)).unwrap();
+        assert_eq!((store.len(), store.platters()), (2, 5));
+        allocate(&mut store, 10);
+        assert_eq!((store.len(), store.platters()), (3, 15));
+        store.abandon(ArrayId(1)).unwrap();
+        store.abandon(ArrayId(2)).unwrap();
+        store.abandon(ArrayId(3)).unwrap();
+        assert!(store.is_empty());
+        assert_eq!(store.platters(), 0);
+    }
+
+    #[test]
+    fn from_parts_counts_platters() {
+        let store = ArrayStore::from_parts(vec![Some(array(4)), None, Some(array(2))], vec![2]);
+        assert_eq!((store.len(), store.platters()), (2, 6));
+    }
+
+    #[test]
+    fn running_out_of_identifiers_fails() {
+        let mut store = ArrayStore {
+            max_id: 3,
+            ..ArrayStore::new()
+        };
+        for _ in 0..3 {
+            allocate(&mut store, 1);
+        }
+        assert!(matches!(
+            store.allocate(array(1)),
+            Err(UmError::ArrayIdsExhausted)
+        ));
+        store.abandon(ArrayId(2)).unwrap();
+        assert_eq!(allocate(&mut store, 1), 2);
+        assert_eq!(store.len(), 3);
+    }
+}
@@ -0,0 +1,719 @@
+//! An assembler for the language that `umdis` and the debugger print.
+//!
+//! A source file has one statement per line, and `;` starts a comment.
+//! Any statement may be preceded by one or more labels, written `name:`,
+//! which stand for the offset of the platter that follows.
+//!
+//! Instructions use the disassembler's mnemonics:
+//!
+//! ```text
+//! cmov rA <- rB if rC        index rA <- rB[rC]        amend rA[rB] <- rC
+//! add rA <- rB, rC           mul rA <- rB, rC          div rA <- rB, rC
+//! nand rA <- rB, rC          halt                      alloc rB <- rC
+//! abandon rC                 output rC                 input rC
+//! loadprog rB, rC            orthography rA <- value
+//! ```
+//!
+//! A value is a decimal number, a `0x` hex number, a character in single
+//! quotes, a constant or a label. Labels may be used before they are
+//! defined; constants may not.
+//!
+//! Pseudo-instructions expand to several instructions:
+//!
+//! - `li rA <- value [, rT]` loads any 32-bit value. Values of 25 bits or
+//!   fewer take one `orthography`, values whose complement fits take an
+//!   `orthography` and a `nand`, and anything else takes five instructions
+//!   and the scratch register `rT`.
+//! - `mov rA <- rB`, `not rA <- rB` and `and rA <- rB, rC` are built from
+//!   `nand`.
+//! - `jmp value, rZ, rT` jumps within array 0, setting `rZ` to 0 and `rT`
+//!   to the target.
+//!
+//! Directives:
+//!
+//! - `.word value, ...` emits each value as a platter.
+//! - `.string "text"` emits one platter per byte. Strings take the same
+//!   escapes as scripts.
+//! - `.const NAME = value` defines a constant.
+//! - `.macro name param, ...` starts a macro, which runs to `.endm`. In the
+//!   body, `\param` is replaced by the argument given for it and `\@` by a
+//!   number unique to each expansion, for making labels. A macro is invoked
+//!   like an instruction: `name arg, ...`.
+
+use crate::instructions::{In, Instruction, Out};
+use crate::machine::Word;
+use crate::script::unescape;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fmt;
+
+/// Largest value an `orthography` can load.
+const MAX_IMMEDIATE: Word = (1 << 25) - 1;
+
+/// How deeply macros may expand inside one another.
+const MAX_EXPANSION_DEPTH: usize = 64;
+
+#[derive(Debug)]
+pub struct AsmError {
+    /// Line of the source, counting from 1. For code expanded from a macro,
+    /// the line that invoked it.
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AsmError {}
+
+/// Assembles `source` into a scroll.
+pub fn assemble(source: &str) -> Result<Vec<Word>, AsmError> {
+    let mut asm = Assembler::default();
+    for (i, line) in source.lines().enumerate() {
+        asm.line(line, 0).map_err(|message| AsmError {
+            line: i + 1,
+            message,
+        })?;
+    }
+    if let Some((line, name, _)) = asm.defining {
+        return Err(AsmError {
+            line,
+            message: format!("macro `{}` has no `.endm`", name),
+        });
+    }
+    asm.items
+        .iter()
+        .map(|(line, item)| {
+            asm.resolve(item).map_err(|message| AsmError {
+                line: *line,
+                message,
+            })
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Assembler {
+    /// Each platter, with the line it came from.
+    items: Vec<(usize, Item)>,
+    labels: HashMap<String, Word>,
+    constants: HashMap<String, Word>,
+    macros: HashMap<String, Macro>,
+    /// The macro whose body is being read: its line, name and contents.
+    defining: Option<(usize, String, Macro)>,
+    /// Expansions so far, for `\@`.
+    expansions: usize,
+    /// Line being assembled, counting from 1.
+    line: usize,
+}
+
+#[derive(Default)]
+struct Macro {
+    params: Vec<String>,
+    body: Vec<String>,
+}
+
+/// One platter, possibly waiting on a label defined further down.
+enum Item {
+    Word(Value),
+    Orthography(Out, Value),
+}
+
+#[derive(Clone)]
+enum Value {
+    Known(Word),
+    Label(String),
+}
+
+impl Assembler {
+    fn line(&mut self, text: &str, depth: usize) -> Result<(), String> {
+        if depth == 0 {
+            self.line += 1;
+        }
+        if let Some((_, _, body)) = &mut self.defining {
+            if first_word(text) == Some(".endm") {
+                let (_, name, body) = self.defining.take().unwrap();
+                self.macros.insert(name, body);
+            } else {
+                body.body.push(text.to_string());
+            }
+            return Ok(());
+        }
+        let tokens = tokenize(text)?;
+        let mut tokens = &tokens[..];
+        // The text after the labels, for macro arguments.
+        let mut statement = text;
+        while let [Token::Ident(name), Token::Colon, rest @ ..] = tokens {
+            self.define_label(name)?;
+            tokens = rest;
+            statement = statement.trim_start()[name.len()..].trim_start()[1..].trim_start();
+        }
+        let Some((Token::Ident(keyword), operands)) = tokens.split_first() else {
+            return match tokens {
+                [] => Ok(()),
+                _ => Err("expected an instruction".to_string()),
+            };
+        };
+        let mut ops = Operands { tokens: operands };
+        match keyword.as_str() {
+            ".word" => {
+                loop {
+                    let value = self.value(&mut ops)?;
+                    self.push(Item::Word(value));
+                    if ops.tokens.is_empty() {
+                        break;
+                    }
+                    ops.expect(Token::Comma)?;
+                }
+                Ok(())
+            }
+            ".string" => match operands {
+                [Token::Str(text)] => {
+                    for &byte in text {
+                        self.push(Item::Word(Value::Known(Word::from(byte))));
+                    }
+                    Ok(())
+                }
+                _ => Err("expected one string".to_string()),
+            },
+            ".const" => {
+                let name = ops.ident()?;
+                ops.expect(Token::Equals)?;
+                let Value::Known(value) = self.value(&mut ops)? else {
+                    return Err("a constant must be known where it is defined".to_string());
+                };
+                ops.end()?;
+                if self.labels.contains_key(&name)
+                    || self.constants.insert(name.clone(), value).is_some()
+                {
+                    return Err(format!("`{}` is already defined", name));
+                }
+                Ok(())
+            }
+            ".macro" => {
+                let name = ops.ident()?;
+                let mut params = Vec::new();
+                while !ops.tokens.is_empty() {
+                    if !params.is_empty() {
+                        ops.expect(Token::Comma)?;
+                    }
+                    params.push(ops.ident()?);
+                }
+                if self.macros.contains_key(&name) {
+                    return Err(format!("macro `{}` is already defined", name));
+                }
+                let body = Macro {
+                    params,
+                    body: Vec::new(),
+                };
+                self.defining = Some((self.line, name, body));
+                Ok(())
+            }
+            ".endm" => Err("`.endm` without `.macro`".to_string()),
+            name if self.macros.contains_key(name) => {
+                self.expand(name, &statement.trim_start()[name.len()..], depth)
+            }
+            mnemonic => self.instruction(mnemonic, &mut ops),
+        }
+    }
+
+    fn define_label(&mut self, name: &str) -> Result<(), String> {
+        let offset = self.items.len() as Word;
+        if self.constants.contains_key(name)
+            || self.labels.insert(name.to_string(), offset).is_some()
+        {
+            return Err(format!("`{}` is already defined", name));
+        }
+        Ok(())
+    }
+
+    fn push(&mut self, item: Item) {
+        self.items.push((self.line, item));
+    }
+
+    fn emit(&mut self, inst: Instruction) {
+        self.push(Item::Word(Value::Known(inst.encode())));
+    }
+
+    /// Expands macro `name`, whose arguments are in `args`.
+    fn expand(&mut self, name: &str, args: &str, depth: usize) -> Result<(), String> {
+        if depth >= MAX_EXPANSION_DEPTH {
+            return Err(format!("macro `{}` expands too deeply", name));
+        }
+        let mac = &self.macros[name];
+        let args = split_args(args);
+        if args.len() != mac.params.len() {
+            return Err(format!(
+                "macro `{}` takes {} arguments, not {}",
+                name,
+                mac.params.len(),
+                args.len()
+            ));
+        }
+        self.expansions += 1;
+        // Longer names first, so that `\ab` is not taken for `\a` then `b`.
+        let mut substitutions: Vec<(String, &str)> = mac
+            .params
+            .iter()
+            .map(|param| format!("\\{}", param))
+            .zip(args)
+            .collect();
+        substitutions.sort_by_key(|(param, _)| std::cmp::Reverse(param.len()));
+        let unique = self.expansions.to_string();
+        let body: Vec<String> = mac
+            .body
+            .iter()
+            .map(|line| {
+                let line = substitutions
+                    .iter()
+                    .fold(line.clone(), |line, (param, arg)| line.replace(param, arg));
+                line.replace("\\@", &unique)
+            })
+            .collect();
+        for line in body {
+            // Name only the outermost macro, which is the one on the line.
+            self.line(&line, depth + 1).map_err(|err| match depth {
+                0 => format!("in macro `{}`: {}", name, err),
+                _ => err,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn instruction(&mut self, mnemonic: &str, ops: &mut Operands) -> Result<(), String> {
+        match mnemonic {
+            "cmov" => {
+                let dest = ops.reg()?;
+                ops.expect(Token::Arrow)?;
+                let src = ops.reg()?;
+                ops.keyword("if")?;
+                let test = ops.reg()?;
+                ops.end()?;
+                self.emit(Instruction::ConditionalMove {
+                    dest: Out::new(dest),
+                    src: In::new(src),
+                    test: In::new(test),
+                });
+            }
+            "index" => {
+                let dest = ops.reg()?;
+                ops.expect(Token::Arrow)?;
+                let array = ops.reg()?;
+                ops.expect(Token::LBracket)?;
+                let offset = ops.reg()?;
+                ops.expect(Token::RBracket)?;
+                ops.end()?;
+                self.emit(Instruction::ArrayIndex {
+                    dest: Out::new(dest),
+                    offset: In::new(offset),
+                    array: In::new(array),
+                });
+            }
+            "amend" => {
+                let array = ops.reg()?;
+                ops.expect(Token::LBracket)?;
+                let offset = ops.reg()?;
+                ops.expect(Token::RBracket)?;
+                ops.expect(Token::Arrow)?;
+                let val = ops.reg()?;
+                ops.end()?;
+                self.emit(Instruction::ArrayAmend {
+                    array: In::new(array),
+                    offset: In::new(offset),
+                    val: In::new(val),
+                });
+            }
+            "add" | "mul" | "div" | "nand" | "and" => {
+                let dest = Out::new(ops.reg()?);
+                ops.expect(Token::Arrow)?;
+                let x = In::new(ops.reg()?);
+                ops.expect(Token::Comma)?;
+                let y = In::new(ops.reg()?);
+                ops.end()?;
+                self.emit(match mnemonic {
+                    "add" => Instruction::Add { dest, x, y },
+                    "mul" => Instruction::Multiply { dest, x, y },
+                    "div" => Instruction::Divide { dest, x, y },
+                    _ => Instruction::Nand { dest, x, y },
+                });
+                if mnemonic == "and" {
+                    self.emit_not(dest.0, dest.0);
+                }
+            }
+            "halt" => {
+                ops.end()?;
+                self.emit(Instruction::Halt);
+            }
+            "alloc" => {
+                let result = ops.reg()?;
+                ops.expect(Token::Arrow)?;
+                let size = ops.reg()?;
+                ops.end()?;
+                self.emit(Instruction::Allocate {
+                    size: In::new(size),
+                    result: Out::new(result),
+                });
+            }
+            "abandon" | "output" | "input" => {
+                let reg = ops.reg()?;
+                ops.end()?;
+                self.emit(match mnemonic {
+                    "abandon" => Instruction::Abandon {
+                        which: In::new(reg),
+                    },
+                    "output" => Instruction::Output { val: In::new(reg) },
+                    _ => Instruction::Input {
+                        dest: Out::new(reg),
+                    },
+                });
+            }
+            "loadprog" => {
+                let from = ops.reg()?;
+                ops.expect(Token::Comma)?;
+                let finger = ops.reg()?;
+                ops.end()?;
+                self.emit(Instruction::LoadProgram {
+                    from: In::new(from),
+                    finger: In::new(finger),
+                });
+            }
+            "orthography" => {
+                let dest = ops.reg()?;
+                ops.expect(Token::Arrow)?;
+                let value = self.value(ops)?;
+                ops.end()?;
+                self.push(Item::Orthography(Out::new(dest), value));
+            }
+            "li" => {
+                let dest = ops.reg()?;
+                ops.expect(Token::Arrow)?;
+                let value = self.value(ops)?;
+                let scratch = match ops.tokens {
+                    [] => None,
+                    _ => {
+                        ops.expect(Token::Comma)?;
+                        Some(ops.reg()?)
+                    }
+                };
+                ops.end()?;
+                self.load_immediate(dest, value, scratch)?;
+            }
+            "mov" | "not" => {
+                let dest = ops.reg()?;
+                ops.expect(Token::Arrow)?;
+                let src = ops.reg()?;
+                ops.end()?;
+                self.emit_not(dest, src);
+                if mnemonic == "mov" {
+                    self.emit_not(dest, dest);
+                }
+            }
+            "jmp" => {
+                let target = self.value(ops)?;
+                ops.expect(Token::Comma)?;
+                let zero = ops.reg()?;
+                ops.expect(Token::Comma)?;
+                let finger = ops.reg()?;
+                ops.end()?;
+                if zero == finger {
+                    return Err("`jmp` needs two different registers".to_string());
+                }
+                self.push(Item::Orthography(Out::new(zero), Value::Known(0)));
+                self.push(Item::Orthography(Out::new(finger), target));
+                self.emit(Instruction::LoadProgram {
+                    from: In::new(zero),
+                    finger: In::new(finger),
+                });
+            }
+            _ => return Err(format!("unknown instruction `{}`", mnemonic)),
+        }
+        Ok(())
+    }
+
+    fn emit_not(&mut self, dest: u8, src: u8) {
+        self.emit(Instruction::Nand {
+            dest: Out::new(dest),
+            x: In::new(src),
+            y: In::new(src),
+        });
+    }
+
+    /// Expands `li`. Labels are assumed to fit in an `orthography`, which
+    /// `resolve` checks once they are known.
+    fn load_immediate(
+        &mut self,
+        dest: u8,
+        value: Value,
+        scratch: Option<u8>,
+    ) -> Result<(), String> {
+        let value = match value {
+            Value::Known(value) if value > MAX_IMMEDIATE => value,
+            value => {
+                self.push(Item::Orthography(Out::new(dest), value));
+                return Ok(());
+            }
+        };
+        if !value <= MAX_IMMEDIATE {
+            self.push(Item::Orthography(Out::new(dest), Value::Known(!value)));
+            self.emit_not(dest, dest);
+            return Ok(());
+        }
+        let scratch = match scratch {
+            Some(scratch) if scratch != dest => scratch,
+            _ => {
+                return Err(format!(
+                    "loading {:#x} needs a scratch register other than r{}",
+                    value, dest
+                ))
+            }
+        };
+        let (dest, scratch) = (Out::new(dest), Out::new(scratch));
+        self.push(Item::Orthography(dest, Value::Known(value >> 16)));
+        self.push(Item::Orthography(scratch, Value::Known(1 << 16)));
+        self.emit(Instruction::Multiply {
+            dest,
+            x: In::new(dest.0),
+            y: In::new(scratch.0),
+        });
+        self.push(Item::Orthography(scratch, Value::Known(value & 0xffff)));
+        self.emit(Instruction::Add {
+            dest,
+            x: In::new(dest.0),
+            y: In::new(scratch.0),
+        });
+        Ok(())
+    }
+
+    fn value(&self, ops: &mut Operands) -> Result<Value, String> {
+        match ops.next()? {
+            Token::Number(value) => Ok(Value::Known(value)),
+            Token::Char(c) => Ok(Value::Known(Word::from(c))),
+            Token::Ident(name) => Ok(match (self.constants.get(&name), self.labels.get(&name)) {
+                (Some(&value), _) | (_, Some(&value)) => Value::Known(value),
+                _ => Value::Label(name),
+            }),
+            token => Err(format!("expected a value, found {}", token)),
+        }
+    }
+
+    fn resolve(&self, item: &Item) -> Result<Word, String> {
+        let known = |value: &Value| match value {
+            Value::Known(value) => Ok(*value),
+            Value::Label(name) => self
+                .labels
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("`{}` is not defined", name)),
+        };
+        match item {
+            Item::Word(value) => known(value),
+            Item::Orthography(dest, value) => {
+                let val = known(value)?;
+                if val > MAX_IMMEDIATE {
+                    return Err(format!(
+                        "{:#x} does not fit in an orthography; use `li`",
+                        val
+                    ));
+                }
+                Ok(Instruction::LoadRegister { dest: *dest, val }.encode())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Ident(String),
+    Number(Word),
+    Char(u8),
+    Str(Vec<u8>),
+    Arrow,
+    Comma,
+    Colon,
+    Equals,
+    LBracket,
+    RBracket,
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "`{}`", name),
+            Token::Number(value) => write!(f, "`{}`", value),
+            Token::Char(c) => write!(f, "{:?}", *c as char),
+            Token::Str(text) => write!(f, "{:?}", String::from_utf8_lossy(text)),
+            Token::Arrow => write!(f, "`<-`"),
+            Token::Comma => write!(f, "`,`"),
+            Token::Colon => write!(f, "`:`"),
+            Token::Equals => write!(f, "`=`"),
+            Token::LBracket => write!(f, "`[`"),
+            Token::RBracket => write!(f, "`]`"),
+        }
+    }
+}
+
+fn tokenize(text: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+    loop {
+        rest = rest.trim_start();
+        let Some(c) = rest.chars().next() else {
+            return Ok(tokens);
+        };
+        let len = match c {
+            ';' => return Ok(tokens),
+            ',' | ':' | '=' | '[' | ']' => {
+                tokens.push(match c {
+                    ',' => Token::Comma,
+                    ':' => Token::Colon,
+                    '=' => Token::Equals,
+                    '[' => Token::LBracket,
+                    _ => Token::RBracket,
+                });
+                1
+            }
+            '<' if rest.starts_with("<-") => {
+                tokens.push(Token::Arrow);
+                2
+            }
+            '"' | '\'' => {
+                let len = quoted_len(rest).ok_or("unterminated quote")?;
+                let text = unescape(&rest[1..len - 1])?;
+                tokens.push(match (c, &text[..]) {
+                    ('"', _) => Token::Str(text),
+                    (_, &[c]) => Token::Char(c),
+                    _ => return Err("a character must be a single byte".to_string()),
+                });
+                len
+            }
+            '0'..='9' => {
+                let len = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric())
+                    .unwrap_or(rest.len());
+                let number = &rest[..len];
+                let parsed = match number.strip_prefix("0x") {
+                    Some(hex) => Word::from_str_radix(hex, 16),
+                    None => number.parse(),
+                };
+                tokens.push(Token::Number(
+                    parsed.map_err(|_| format!("`{}` is not a 32-bit number", number))?,
+                ));
+                len
+            }
+            _ if c == '.' || c == '_' || c.is_ascii_alphabetic() => {
+                let len = rest[1..]
+                    .find(|c: char| c != '_' && !c.is_ascii_alphanumeric())
+                    .map_or(rest.len(), |len| len + 1);
+                tokens.push(Token::Ident(rest[..len].to_string()));
+                len
+            }
+            _ => return Err(format!("unexpected `{}`", c)),
+        };
+        rest = &rest[len..];
+    }
+}
+
+/// Length of the quoted text at the start of `text`, quotes included.
+fn quoted_len(text: &str) -> Option<usize> {
+    let quote = text.chars().next()?;
+    let mut escaped = false;
+    for (i, c) in text.char_indices().skip(1) {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' => escaped = true,
+            _ if c == quote => return Some(i + 1),
+            _ => {}
+        }
+    }
+    None
+}
+
+fn first_word(text: &str) -> Option<&str> {
+    text.split_whitespace().next()
+}
+
+/// Splits macro arguments at the commas outside quotes, stopping at a
+/// comment.
+fn split_args(text: &str) -> Vec<&str> {
+    let mut args = Vec::new();
+    let mut start = 0;
+    let mut i = 0;
+    let end = loop {
+        let Some(c) = text[i..].chars().next() else {
+            break text.len();
+        };
+        match c {
+            '"' | '\'' => {
+                if let Some(len) = quoted_len(&text[i..]) {
+                    i += len;
+                    continue;
+                }
+            }
+            ',' => {
+                args.push(text[start..i].trim());
+                start = i + 1;
+            }
+            ';' => break i,
+            _ => {}
+        }
+        i += c.len_utf8();
+    };
+    let last = text[start..end].trim();
+    if !(args.is_empty() && last.is_empty()) {
+        args.push(last);
+    }
+    args
+}
+
+/// The operands of a statement, consumed from the front.
+struct Operands<'a> {
+    tokens: &'a [Token],
+}
+
+impl Operands<'_> {
+    fn next(&mut self) -> Result<Token, String> {
+        let (token, rest) = self.tokens.split_first().ok_or("missing operand")?;
+        self.tokens = rest;
+        Ok(token.clone())
+    }
+
+    fn expect(&mut self, expected: Token) -> Result<(), String> {
+        match self.next()? {
+            token if token == expected => Ok(()),
+            token => Err(format!("expected {}, found {}", expected, token)),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> Result<(), String> {
+        self.expect(Token::Ident(keyword.to_string()))
+    }
+
+    fn ident(&mut self) -> Result<String, String> {
+        match self.next()? {
+            Token::Ident(name) => Ok(name),
+            token => Err(format!("expected a name, found {}", token)),
+        }
+    }
+
+    fn reg(&mut self) -> Result<u8, String> {
+        let token = self.next()?;
+        if let Token::Ident(name) = &token {
+            if let Some(idx) = name.strip_prefix('r').and_then(|idx| idx.parse().ok()) {
+                if idx < 8 {
+                    return Ok(idx);
+                }
+            }
+        }
+        Err(format!("expected a register, found {}", token))
+    }
+
+    fn end(&self) -> Result<(), String> {
+        match self.tokens.first() {
+            None => Ok(()),
+            Some(token) => Err(format!("unexpected {}", token)),
+        }
+    }
+}
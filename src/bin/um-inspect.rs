@@ -0,0 +1,97 @@
+//! Examines a crash dump written by `--crash-dump`.
+//!
+//! Usage:
+//!
+//! - `um-inspect <dump>` prints the fault, the registers, the active arrays
+//!   and the code around the faulting finger.
+//! - `um-inspect <dump> disasm <finger> [count]` disassembles array 0.
+//! - `um-inspect <dump> array <id> [offset] [len]` prints an array's platters.
+//!
+//! Numbers may be decimal or `0x`-prefixed hex.
+
+use an_urgent_appeal::dump::CrashDump;
+use an_urgent_appeal::instructions::ArrayId;
+use an_urgent_appeal::listing::{parse_number, HexDump, Line};
+use an_urgent_appeal::machine::Word;
+use std::env;
+use std::fs::File;
+use std::io::BufReader;
+use std::process;
+
+const USAGE: &str =
+    "usage: um-inspect <dump> [disasm <finger> [count] | array <id> [offset] [len]]";
+
+/// Platters shown either side of the faulting finger.
+const CONTEXT: Word = 8;
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let Some((path, command)) = args.split_first() else {
+        usage();
+    };
+    let file = File::open(path).unwrap_or_else(|err| fail(&format!("{}: {}", path, err)));
+    let dump = CrashDump::read_from(&mut BufReader::new(file))
+        .unwrap_or_else(|err| fail(&format!("{}: {}", path, err)));
+    let numbers: Vec<Word> = command
+        .iter()
+        .skip(1)
+        .map(|arg| parse_number(arg).unwrap_or_else(|| usage()))
+        .collect();
+    match (command.first().map(String::as_str), &numbers[..]) {
+        (None, []) => summary(&dump),
+        (Some("disasm"), &[finger]) => disasm(&dump, finger, 16),
+        (Some("disasm"), &[finger, count]) => disasm(&dump, finger, count),
+        (Some("array"), &[id]) => array(&dump, ArrayId(id), 0, Word::MAX),
+        (Some("array"), &[id, offset]) => array(&dump, ArrayId(id), offset, Word::MAX),
+        (Some("array"), &[id, offset, len]) => array(&dump, ArrayId(id), offset, len),
+        _ => usage(),
+    }
+}
+
+fn summary(dump: &CrashDump) {
+    println!("{}", dump.message);
+    println!();
+    println!("arrays:");
+    for (id, array) in &dump.arrays {
+        println!("  {:#010x}: {} platters", id.0, array.len());
+    }
+    println!();
+    let start = dump.finger.saturating_sub(CONTEXT);
+    disasm(dump, start, dump.finger - start + CONTEXT + 1);
+}
+
+fn disasm(dump: &CrashDump, start: Word, count: Word) {
+    let program = dump.array(ArrayId(0)).unwrap_or_default();
+    for finger in start..start.saturating_add(count) {
+        let Some(&platter) = program.get(finger as usize) else {
+            break;
+        };
+        let mark = if finger == dump.finger { '>' } else { ' ' };
+        println!("{}{}", mark, Line { finger, platter });
+    }
+}
+
+fn array(dump: &CrashDump, id: ArrayId, offset: Word, len: Word) {
+    let Some(platters) = dump.array(id) else {
+        fail(&format!("array {:#x} is not in the dump", id.0));
+    };
+    let start = (offset as usize).min(platters.len());
+    let end = start.saturating_add(len as usize).min(platters.len());
+    print!(
+        "{}",
+        HexDump {
+            start,
+            platters: &platters[start..end],
+        }
+    );
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("um-inspect: {}", message);
+    process::exit(1);
+}
@@ -0,0 +1,371 @@
+//! Translates a UM scroll into a standalone Rust program.
+//!
+//! Every finger that can be the target of a jump within array 0 starts a
+//! block of straight-line Rust code. Targets are found by following the
+//! constants that `LoadRegister` puts into the finger register of a
+//! `LoadProgram`. Jumps to anywhere else, loads from other arrays, writes to
+//! array 0 and undecodable platters fall back to an interpreter embedded in
+//! the generated file, which hands control back to native code whenever the
+//! program jumps to a known block and array 0 is still the original scroll.
+//!
+//! Usage: `um2rs <scroll> [output.rs]`. Writes to stdout by default.
+//!
+//! The translated program exits with status 0 when the scroll halts, 1 when
+//! the machine fails and 3 when it runs off the end of array 0, as
+//! `an_urgent_appeal` does.
+
+use an_urgent_appeal::analysis::jump_targets;
+use an_urgent_appeal::instructions::Instruction;
+use an_urgent_appeal::machine::{Machine, Word};
+use std::collections::BTreeSet;
+use std::env;
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, Write};
+use std::process;
+
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("usage: {} <scroll> [output.rs]", args[0]);
+        process::exit(2);
+    }
+    let filename = &args[1];
+    let bytes = fs::read(filename).unwrap_or_else(|err| fail(&format!("{}: {}", filename, err)));
+    let program = Machine::load_program_from_bytes(bytes);
+
+    let leaders = find_leaders(&program);
+    let source = translate(filename, &program, &leaders);
+    eprintln!(
+        "{}: {} platters, {} blocks",
+        filename,
+        program.len(),
+        leaders.len()
+    );
+
+    match args.get(2) {
+        Some(out) => {
+            fs::write(out, source).unwrap_or_else(|err| fail(&format!("{}: {}", out, err)))
+        }
+        None => io::stdout()
+            .write_all(source.as_bytes())
+            .unwrap_or_else(|err| fail(&format!("unable to write output: {}", err))),
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("um2rs: {}", message);
+    process::exit(1);
+}
+
+/// Returns the fingers that start a block: the start of the program and every
+/// `LoadProgram` target that can be determined statically.
+fn find_leaders(program: &[Word]) -> BTreeSet<Word> {
+    let mut leaders: BTreeSet<Word> = jump_targets(program).into_values().collect();
+    leaders.insert(0);
+    leaders
+}
+
+fn translate(filename: &str, program: &[Word], leaders: &BTreeSet<Word>) -> String {
+    let mut out = String::new();
+    writeln!(out, "// Generated by um2rs from {}. Do not edit.", filename).unwrap();
+    out.push_str(PRELUDE);
+
+    writeln!(out, "static SCROLL: [u32; {}] = [", program.len()).unwrap();
+    for chunk in program.chunks(8) {
+        let words: Vec<String> = chunk.iter().map(|w| format!("{:#010x}", w)).collect();
+        writeln!(out, "    {},", words.join(", ")).unwrap();
+    }
+    out.push_str("];\n\n");
+
+    let list: Vec<String> = leaders.iter().map(|l| l.to_string()).collect();
+    writeln!(
+        out,
+        "static LEADERS: [u32; {}] = [{}];\n",
+        leaders.len(),
+        list.join(", ")
+    )
+    .unwrap();
+
+    out.push_str("/// Runs the scroll. Returns `None` once the machine halts.\n");
+    out.push_str("fn execute(m: &mut Machine) -> Option<()> {\n");
+    out.push_str("    let mut pc: u32 = 0;\n");
+    out.push_str("    loop {\n");
+    out.push_str("        pc = match pc {\n");
+    for &leader in leaders {
+        writeln!(out, "            {} => 'block: {{", leader).unwrap();
+        translate_block(&mut out, program, leaders, leader);
+        out.push_str("            }\n");
+    }
+    out.push_str("            _ => m.run(pc)?,\n");
+    out.push_str("        };\n");
+    out.push_str("    }\n");
+    out.push_str("}\n");
+    out.push_str(RUNTIME);
+    out
+}
+
+/// Emits the body of the block starting at `leader`. The block evaluates to
+/// the finger to continue at.
+fn translate_block(out: &mut String, program: &[Word], leaders: &BTreeSet<Word>, leader: Word) {
+    const INDENT: &str = "                ";
+    let mut finger = leader;
+    loop {
+        if finger as usize >= program.len() || (finger != leader && leaders.contains(&finger)) {
+            writeln!(out, "{}{}", INDENT, finger).unwrap();
+            return;
+        }
+        let next = finger + 1;
+        let inst = match Instruction::decode_from(program[finger as usize]) {
+            Ok(inst) => inst,
+            Err(_) => {
+                writeln!(out, "{}m.run({})?", INDENT, finger).unwrap();
+                return;
+            }
+        };
+        let stmt = match inst {
+            Instruction::ConditionalMove { dest, src, test } => format!(
+                "if m.r[{}] != 0 {{ m.r[{}] = m.r[{}]; }}",
+                test.idx, dest.0, src.idx
+            ),
+            Instruction::ArrayIndex {
+                dest,
+                offset,
+                array,
+            } => format!(
+                "m.r[{}] = m.index(m.r[{}], m.r[{}]);",
+                dest.0, array.idx, offset.idx
+            ),
+            Instruction::ArrayAmend { array, offset, val } => format!(
+                "if m.amend(m.r[{}], m.r[{}], m.r[{}]) {{ break 'block m.run({})?; }}",
+                array.idx, offset.idx, val.idx, next
+            ),
+            Instruction::Add { dest, x, y } => format!(
+                "m.r[{}] = m.r[{}].wrapping_add(m.r[{}]);",
+                dest.0, x.idx, y.idx
+            ),
+            Instruction::Multiply { dest, x, y } => format!(
+                "m.r[{}] = m.r[{}].wrapping_mul(m.r[{}]);",
+                dest.0, x.idx, y.idx
+            ),
+            Instruction::Divide { dest, x, y } => format!(
+                "m.r[{}] = m.divide(m.r[{}], m.r[{}]);",
+                dest.0, x.idx, y.idx
+            ),
+            Instruction::Nand { dest, x, y } => {
+                format!("m.r[{}] = !(m.r[{}] & m.r[{}]);", dest.0, x.idx, y.idx)
+            }
+            Instruction::Halt => {
+                writeln!(out, "{}return None;", INDENT).unwrap();
+                return;
+            }
+            Instruction::Allocate { size, result } => {
+                format!("m.r[{}] = m.alloc(m.r[{}]);", result.0, size.idx)
+            }
+            Instruction::Abandon { which } => format!("m.abandon(m.r[{}]);", which.idx),
+            Instruction::Output { val } => format!("m.output(m.r[{}]);", val.idx),
+            Instruction::Input { dest } => format!("m.r[{}] = m.input();", dest.0),
+            Instruction::LoadProgram { from, finger } => {
+                writeln!(
+                    out,
+                    "{}let (from, to) = (m.r[{}], m.r[{}]);",
+                    INDENT, from.idx, finger.idx
+                )
+                .unwrap();
+                writeln!(out, "{}if from == 0 {{ break 'block to; }}", INDENT).unwrap();
+                writeln!(out, "{}m.load(from);", INDENT).unwrap();
+                writeln!(out, "{}m.run(to)?", INDENT).unwrap();
+                return;
+            }
+            Instruction::LoadRegister { dest, val } => {
+                format!("m.r[{}] = {:#x};", dest.0, val)
+            }
+        };
+        writeln!(out, "{}{}", INDENT, stmt).unwrap();
+        finger = next;
+    }
+}
+
+const PRELUDE: &str = r#"
+#![allow(unreachable_code, unused_labels, clippy::all)]
+
+use std::io::{self, BufWriter, Read, Stdin, Stdout, Write};
+
+fn main() {
+    let mut m = Machine::new(SCROLL.to_vec());
+    execute(&mut m);
+    m.flush();
+}
+
+"#;
+
+const RUNTIME: &str = r#"
+/// A copy of the universal machine, used wherever native code cannot run.
+struct Machine {
+    r: [u32; 8],
+    program: Vec<u32>,
+    /// Whether array 0 still holds `SCROLL`, so native code may run.
+    pristine: bool,
+    /// Slot `i` holds the array identified by `i + 1`.
+    arrays: Vec<Option<Vec<u32>>>,
+    free: Vec<u32>,
+    stdin: io::Bytes<Stdin>,
+    stdout: BufWriter<Stdout>,
+}
+
+impl Machine {
+    fn new(program: Vec<u32>) -> Machine {
+        Machine {
+            r: [0; 8],
+            program,
+            pristine: true,
+            arrays: Vec::new(),
+            free: Vec::new(),
+            stdin: io::stdin().bytes(),
+            stdout: BufWriter::new(io::stdout()),
+        }
+    }
+
+    fn fail(&mut self, reason: &str) -> ! {
+        self.stop(1, reason)
+    }
+
+    fn stop(&mut self, status: i32, reason: &str) -> ! {
+        self.flush();
+        eprintln!("machine failure: {}", reason);
+        std::process::exit(status);
+    }
+
+    fn flush(&mut self) {
+        let _ = self.stdout.flush();
+    }
+
+    fn array(&mut self, id: u32) -> &mut Vec<u32> {
+        if id == 0 {
+            return &mut self.program;
+        }
+        match self.arrays.get_mut(id as usize - 1) {
+            Some(Some(_)) => self.arrays[id as usize - 1].as_mut().unwrap(),
+            _ => self.fail("invalid array id"),
+        }
+    }
+
+    fn index(&mut self, id: u32, offset: u32) -> u32 {
+        match self.array(id).get(offset as usize) {
+            Some(&val) => val,
+            None => self.fail("array index out of range"),
+        }
+    }
+
+    /// Returns true if array 0 was written, in which case native code for
+    /// the original scroll no longer applies.
+    fn amend(&mut self, id: u32, offset: u32, val: u32) -> bool {
+        match self.array(id).get_mut(offset as usize) {
+            Some(slot) => *slot = val,
+            None => self.fail("array amendment out of range"),
+        }
+        if id == 0 {
+            self.pristine = false;
+        }
+        id == 0
+    }
+
+    fn divide(&mut self, x: u32, y: u32) -> u32 {
+        match x.checked_div(y) {
+            Some(result) => result,
+            None => self.fail("division by zero"),
+        }
+    }
+
+    fn alloc(&mut self, size: u32) -> u32 {
+        let array = vec![0; size as usize];
+        match self.free.pop() {
+            Some(id) => {
+                self.arrays[id as usize - 1] = Some(array);
+                id
+            }
+            None => {
+                self.arrays.push(Some(array));
+                self.arrays.len() as u32
+            }
+        }
+    }
+
+    fn abandon(&mut self, id: u32) {
+        if id == 0 {
+            self.fail("cannot abandon the program");
+        }
+        match self.arrays.get_mut(id as usize - 1).and_then(|slot| slot.take()) {
+            Some(_) => self.free.push(id),
+            None => self.fail("invalid array id"),
+        }
+    }
+
+    fn output(&mut self, val: u32) {
+        if val > 255 {
+            self.fail("invalid output");
+        }
+        let _ = self.stdout.write_all(&[val as u8]);
+    }
+
+    fn input(&mut self) -> u32 {
+        self.flush();
+        match self.stdin.next() {
+            Some(Ok(byte)) => u32::from(byte),
+            _ => u32::MAX,
+        }
+    }
+
+    fn load(&mut self, id: u32) {
+        let program = self.array(id).clone();
+        self.program = program;
+        self.pristine = false;
+    }
+
+    /// Interprets from `finger` until the program jumps to a known block of
+    /// the original scroll. Returns that block, or `None` once halted.
+    fn run(&mut self, mut finger: u32) -> Option<u32> {
+        loop {
+            let word = match self.program.get(finger as usize) {
+                Some(&word) => word,
+                None => self.stop(3, "ran off the end of the program"),
+            };
+            finger += 1;
+            let a = (word >> 6 & 7) as usize;
+            let b = (word >> 3 & 7) as usize;
+            let c = (word & 7) as usize;
+            match word >> 28 {
+                0 => {
+                    if self.r[c] != 0 {
+                        self.r[a] = self.r[b];
+                    }
+                }
+                1 => self.r[a] = self.index(self.r[b], self.r[c]),
+                2 => {
+                    self.amend(self.r[a], self.r[b], self.r[c]);
+                }
+                3 => self.r[a] = self.r[b].wrapping_add(self.r[c]),
+                4 => self.r[a] = self.r[b].wrapping_mul(self.r[c]),
+                5 => self.r[a] = self.divide(self.r[b], self.r[c]),
+                6 => self.r[a] = !(self.r[b] & self.r[c]),
+                7 => return None,
+                8 => self.r[b] = self.alloc(self.r[c]),
+                9 => self.abandon(self.r[c]),
+                10 => self.output(self.r[c]),
+                11 => self.r[c] = self.input(),
+                12 => {
+                    if self.r[b] != 0 {
+                        self.load(self.r[b]);
+                    }
+                    finger = self.r[c];
+                    if self.pristine && LEADERS.binary_search(&finger).is_ok() {
+                        return Some(finger);
+                    }
+                }
+                13 => self.r[(word >> 25 & 7) as usize] = word & 0x1ff_ffff,
+                _ => self.fail("unknown instruction"),
+            }
+        }
+    }
+}
+"#;
@@ -0,0 +1,54 @@
+//! Assembles a UM scroll from the language `umdis` prints.
+//!
+//! Usage: `umasm [-o <scroll>] <source>`
+//!
+//! The scroll is written big-endian to `<scroll>`, or by default to the
+//! source's path with the extension `.um`. See `an_urgent_appeal::asm` for
+//! the language.
+
+use an_urgent_appeal::asm::assemble;
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+const USAGE: &str = "usage: umasm [-o <scroll>] <source>";
+
+fn main() {
+    let mut source = None;
+    let mut output = None;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-o" => output = Some(PathBuf::from(args.next().unwrap_or_else(|| usage()))),
+            _ if arg.starts_with('-') || source.is_some() => usage(),
+            _ => source = Some(PathBuf::from(arg)),
+        }
+    }
+    let Some(source) = source else {
+        usage();
+    };
+    let output = output.unwrap_or_else(|| source.with_extension("um"));
+    let text = fs::read_to_string(&source)
+        .unwrap_or_else(|err| fail(&format!("{}: {}", source.display(), err)));
+    let scroll = assemble(&text).unwrap_or_else(|err| {
+        fail(&format!(
+            "{}:{}: {}",
+            source.display(),
+            err.line,
+            err.message
+        ))
+    });
+    let bytes: Vec<u8> = scroll.iter().flat_map(|word| word.to_be_bytes()).collect();
+    fs::write(&output, bytes).unwrap_or_else(|err| fail(&format!("{}: {}", output.display(), err)));
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("umasm: {}", message);
+    process::exit(1);
+}
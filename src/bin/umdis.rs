@@ -0,0 +1,149 @@
+//! Disassembles a UM scroll.
+//!
+//! Usage: `umdis [--from <finger>] [--to <finger>] [--targets] [--data] <scroll>`
+//!
+//! Each platter is printed as its offset, its hex value and its mnemonic.
+//! Platters that do not decode are shown as `.data`.
+//!
+//! - `--from` and `--to` limit the listing to the half-open range of offsets
+//!   between them.
+//! - `--targets` puts a label before every statically known jump target and
+//!   notes the target after each `LoadProgram` that jumps there.
+//! - `--data` also shows platters as `.data` when they decode but look like
+//!   data, because they set any of bits 9 to 23, which no operator uses and
+//!   code leaves clear. Data platters are shown as ASCII where printable.
+//!
+//! Numbers may be decimal or `0x`-prefixed hex.
+
+use an_urgent_appeal::analysis::jump_targets;
+use an_urgent_appeal::instructions::Instruction;
+use an_urgent_appeal::listing;
+use an_urgent_appeal::machine::{Machine, Word};
+use std::collections::{BTreeMap, BTreeSet};
+use std::env;
+use std::fs;
+use std::io::{self, BufWriter, Write};
+use std::process;
+
+const USAGE: &str = "usage: umdis [--from <finger>] [--to <finger>] [--targets] [--data] <scroll>";
+
+struct Options {
+    filename: String,
+    from: Word,
+    to: Word,
+    targets: bool,
+    data: bool,
+}
+
+fn parse_args() -> Options {
+    let mut filename = None;
+    let mut from = 0;
+    let mut to = Word::MAX;
+    let mut targets = false;
+    let mut data = false;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--from" => from = parse_number(args.next()),
+            "--to" => to = parse_number(args.next()),
+            "--targets" => targets = true,
+            "--data" => data = true,
+            _ if arg.starts_with("--") || filename.is_some() => usage(),
+            _ => filename = Some(arg),
+        }
+    }
+    match filename {
+        Some(filename) => Options {
+            filename,
+            from,
+            to,
+            targets,
+            data,
+        },
+        None => usage(),
+    }
+}
+
+fn parse_number(arg: Option<String>) -> Word {
+    arg.as_deref()
+        .and_then(listing::parse_number)
+        .unwrap_or_else(|| usage())
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("umdis: {}", message);
+    process::exit(1);
+}
+
+fn main() {
+    let options = parse_args();
+    let bytes = fs::read(&options.filename)
+        .unwrap_or_else(|err| fail(&format!("{}: {}", options.filename, err)));
+    let program = Machine::load_program_from_bytes(bytes);
+    let jumps = if options.targets {
+        jump_targets(&program)
+    } else {
+        BTreeMap::new()
+    };
+    if let Err(err) = list(&program, &options, &jumps) {
+        // Stopping early because the reader has gone, as with `| head`, is fine.
+        if err.kind() != io::ErrorKind::BrokenPipe {
+            fail(&err.to_string());
+        }
+    }
+}
+
+fn list(program: &[Word], options: &Options, jumps: &BTreeMap<Word, Word>) -> io::Result<()> {
+    let labels: BTreeSet<Word> = jumps.values().copied().collect();
+    let mut out = BufWriter::new(io::stdout().lock());
+    let end = (options.to as usize).min(program.len());
+    let start = (options.from as usize).min(end);
+    for (finger, &word) in program[start..end].iter().enumerate() {
+        let finger = (start + finger) as Word;
+        if labels.contains(&finger) {
+            writeln!(out, "label_{:08x}:", finger)?;
+        }
+        let line = match Instruction::decode_from(word) {
+            Ok(inst) if !(options.data && looks_like_data(word)) => match jumps.get(&finger) {
+                Some(target) => format!("{:<32}; -> label_{:08x}", inst.to_string(), target),
+                None => inst.to_string(),
+            },
+            _ if options.data => match ascii(word) {
+                Some(text) => format!("{:<32}; {:?}", ".data", text),
+                None => ".data".to_string(),
+            },
+            _ => ".data".to_string(),
+        };
+        writeln!(out, "{:#010x}: {:08x}  {}", finger, word, line)?;
+    }
+    out.flush()
+}
+
+/// Whether a platter that decodes sets bits its operator ignores. The bits
+/// just below the operator are left out, since some code sets them: the
+/// sandmark does so to check that they are ignored.
+fn looks_like_data(word: Word) -> bool {
+    let unused = match word >> 28 {
+        // Orthography uses every bit.
+        13 => 0,
+        // Halt uses no registers.
+        7 => 0x00ff_ffff,
+        _ => 0x00ff_fe00,
+    };
+    word & unused != 0
+}
+
+/// The platter's bytes as text, if they are all printable ASCII.
+fn ascii(word: Word) -> Option<String> {
+    let bytes = word.to_be_bytes();
+    if bytes.iter().all(|&b| b.is_ascii_graphic() || b == b' ') {
+        Some(bytes.iter().map(|&b| b as char).collect())
+    } else {
+        None
+    }
+}
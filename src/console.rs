@@ -0,0 +1,220 @@
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, IsTerminal, Read, Stdin, Stdout, Write};
+use std::sync::{Arc, Mutex};
+
+/// The machine's console: the source of `Input` and the sink of `Output`.
+///
+/// Consoles are `Send` so that a machine can be built on one thread and run
+/// on another.
+pub trait Console: Send {
+    /// Reads one byte, or `None` once the input is exhausted.
+    fn read_byte(&mut self) -> io::Result<Option<u8>>;
+
+    /// Reads one byte for an `Input` that the machine executed as its nth
+    /// instruction. Consoles that care when input is read override this.
+    fn read_byte_at(&mut self, _n: u64) -> io::Result<Option<u8>> {
+        self.read_byte()
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()>;
+
+    /// Writes one byte for an `Output` that the machine executed as its nth
+    /// instruction.
+    fn write_byte_at(&mut self, _n: u64, byte: u8) -> io::Result<()> {
+        self.write_byte(byte)
+    }
+
+    /// Pushes out any buffered output. The machine calls this before every
+    /// `Input` and when it stops.
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Input already taken from the underlying source but not yet read by
+    /// the machine, which a snapshot has to carry over.
+    fn pending_input(&self) -> Vec<u8> {
+        Vec::new()
+    }
+}
+
+/// Reads from stdin and writes to stdout, both as raw bytes and buffered.
+/// Output to a terminal is also flushed at the end of every line.
+pub struct StdioConsole {
+    stdin: BufReader<Stdin>,
+    stdout: BufWriter<Stdout>,
+    line_buffered: bool,
+}
+
+impl StdioConsole {
+    pub fn new() -> StdioConsole {
+        StdioConsole {
+            stdin: BufReader::new(io::stdin()),
+            stdout: BufWriter::new(io::stdout()),
+            line_buffered: io::stdout().is_terminal(),
+        }
+    }
+}
+
+impl Default for StdioConsole {
+    fn default() -> StdioConsole {
+        StdioConsole::new()
+    }
+}
+
+impl Console for StdioConsole {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        read_byte(&mut self.stdin)
+    }
+
+    /// Characters are unsigned 8-bit values, not code points, so values
+    /// above 127 are written as a single byte rather than as UTF-8.
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.stdout.write_all(&[byte])?;
+        if self.line_buffered && byte == b'\n' {
+            self.stdout.flush()?;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stdout.flush()
+    }
+
+    fn pending_input(&self) -> Vec<u8> {
+        self.stdin.buffer().to_vec()
+    }
+}
+
+/// Reads from and writes to memory.
+///
+/// Clones share the same buffers, so a clone kept outside the machine can
+/// supply more input and inspect the output while or after it runs.
+#[derive(Clone, Default)]
+pub struct BufferConsole {
+    input: Arc<Mutex<VecDeque<u8>>>,
+    output: Arc<Mutex<Vec<u8>>>,
+}
+
+impl BufferConsole {
+    pub fn new(input: &[u8]) -> BufferConsole {
+        let console = BufferConsole::default();
+        console.push_input(input);
+        console
+    }
+
+    pub fn push_input(&self, input: &[u8]) {
+        self.input.lock().unwrap().extend(input);
+    }
+
+    /// Everything written so far.
+    pub fn output(&self) -> Vec<u8> {
+        self.output.lock().unwrap().clone()
+    }
+}
+
+impl Console for BufferConsole {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        Ok(self.input.lock().unwrap().pop_front())
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.output.lock().unwrap().push(byte);
+        Ok(())
+    }
+
+    fn pending_input(&self) -> Vec<u8> {
+        self.input.lock().unwrap().iter().copied().collect()
+    }
+}
+
+/// Reads from one file and writes to another.
+pub struct FileConsole {
+    input: BufReader<File>,
+    output: BufWriter<File>,
+}
+
+impl FileConsole {
+    pub fn new(input: File, output: File) -> FileConsole {
+        FileConsole {
+            input: BufReader::new(input),
+            output: BufWriter::new(output),
+        }
+    }
+}
+
+impl Console for FileConsole {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        read_byte(&mut self.input)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.output.write_all(&[byte])
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+
+    fn pending_input(&self) -> Vec<u8> {
+        self.input.buffer().to_vec()
+    }
+}
+
+/// Hands out input left over from a snapshot before reading from the
+/// console it wraps.
+pub struct ResumedConsole {
+    pending: VecDeque<u8>,
+    inner: Box<dyn Console>,
+}
+
+impl ResumedConsole {
+    pub fn new(pending: Vec<u8>, inner: Box<dyn Console>) -> ResumedConsole {
+        ResumedConsole {
+            pending: VecDeque::from(pending),
+            inner,
+        }
+    }
+}
+
+impl Console for ResumedConsole {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        match self.pending.pop_front() {
+            Some(byte) => Ok(Some(byte)),
+            None => self.inner.read_byte(),
+        }
+    }
+
+    fn read_byte_at(&mut self, n: u64) -> io::Result<Option<u8>> {
+        match self.pending.pop_front() {
+            Some(byte) => Ok(Some(byte)),
+            None => self.inner.read_byte_at(n),
+        }
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.inner.write_byte(byte)
+    }
+
+    fn write_byte_at(&mut self, n: u64, byte: u8) -> io::Result<()> {
+        self.inner.write_byte_at(n, byte)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+
+    fn pending_input(&self) -> Vec<u8> {
+        let mut pending: Vec<u8> = self.pending.iter().copied().collect();
+        pending.extend(self.inner.pending_input());
+        pending
+    }
+}
+
+fn read_byte<R: Read>(reader: &mut BufReader<R>) -> io::Result<Option<u8>> {
+    let mut byte = [0];
+    match reader.read(&mut byte)? {
+        0 => Ok(None),
+        _ => Ok(Some(byte[0])),
+    }
+}
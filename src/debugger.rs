@@ -0,0 +1,259 @@
+use an_urgent_appeal::instructions::ArrayId;
+use an_urgent_appeal::listing::{parse_number, HexDump, Line};
+use an_urgent_appeal::machine::{ExitReason, Machine, StepResult, Word};
+use std::collections::BTreeSet;
+use std::io::{self, BufRead, Write};
+
+const HELP: &str = "\
+commands:
+  step [n]                  execute n instructions (default 1)
+  continue                  run until a breakpoint or the machine stops
+  break <finger>            toggle a breakpoint; with no finger, list them
+  regs                      show the registers
+  dump <array> <off> <len>  show len platters of an array from off
+  disasm <finger> [n]       disassemble n platters of array 0 (default 10)
+  finger                    show the finger and the instruction under it
+  help                      show this message
+  quit                      stop debugging
+numbers may be decimal or 0x-prefixed hex";
+
+/// An interactive debugger reading commands from stdin.
+///
+/// The program's own `Input` also reads from stdin, so lines meant for the
+/// program are typed at the debugger prompt only while it is waiting.
+///
+/// `step` and `continue` stop early if the machine is cancelled, though not
+/// while it is blocked reading input.
+pub struct Debugger {
+    machine: Machine,
+    breakpoints: BTreeSet<Word>,
+    /// Why the machine stopped, once it has.
+    stopped: Option<ExitReason>,
+}
+
+impl Debugger {
+    pub fn new(machine: Machine) -> Debugger {
+        Debugger {
+            machine,
+            breakpoints: BTreeSet::new(),
+            stopped: None,
+        }
+    }
+
+    /// Reads commands until `quit` or the end of stdin, then hands back the
+    /// machine and why it stopped, if it did.
+    pub fn run(mut self) -> io::Result<(Machine, Option<ExitReason>)> {
+        let stdin = io::stdin();
+        self.show_finger();
+        loop {
+            print!("(um) ");
+            io::stdout().flush()?;
+            let mut line = String::new();
+            if stdin.lock().read_line(&mut line)? == 0 {
+                return Ok((self.machine, self.stopped));
+            }
+            let words: Vec<&str> = line.split_whitespace().collect();
+            let Some((&command, args)) = words.split_first() else {
+                continue;
+            };
+            let result = match command {
+                "step" | "s" => self.step(args),
+                "continue" | "c" => self.continue_(args),
+                "break" | "b" => self.toggle_breakpoint(args),
+                "regs" | "r" => self.regs(args),
+                "dump" | "x" => self.dump(args),
+                "disasm" | "d" => self.disasm(args),
+                "finger" | "f" => self.finger(args),
+                "help" | "h" => {
+                    println!("{}", HELP);
+                    Ok(())
+                }
+                "quit" | "q" => return Ok((self.machine, self.stopped)),
+                _ => Err(format!("unknown command `{}`, try `help`", command)),
+            };
+            if let Err(message) = result {
+                println!("{}", message);
+            }
+        }
+    }
+
+    fn step(&mut self, args: &[&str]) -> Result<(), String> {
+        let [count] = optional_args(args, [1])?;
+        if self.stopped.is_none() {
+            let result = self.machine.run_for(u64::from(count));
+            self.stop_on(result);
+        }
+        self.flush();
+        self.show_finger();
+        Ok(())
+    }
+
+    fn continue_(&mut self, args: &[&str]) -> Result<(), String> {
+        let [] = optional_args(args, [])?;
+        // Always move off the current finger, so a breakpoint there does not
+        // stop the machine again straight away.
+        if self.step_once() {
+            let breakpoints = &self.breakpoints;
+            let result = self
+                .machine
+                .run_until(|m| breakpoints.contains(&m.finger()));
+            self.stop_on(result);
+        }
+        self.flush();
+        self.show_finger();
+        Ok(())
+    }
+
+    fn toggle_breakpoint(&mut self, args: &[&str]) -> Result<(), String> {
+        match args {
+            [] => {
+                for finger in &self.breakpoints {
+                    println!("breakpoint at {:#x}", finger);
+                }
+            }
+            [finger] => {
+                let finger = number(finger)?;
+                if self.breakpoints.remove(&finger) {
+                    println!("removed breakpoint at {:#x}", finger);
+                } else {
+                    self.breakpoints.insert(finger);
+                    println!("breakpoint at {:#x}", finger);
+                }
+            }
+            _ => return Err("usage: break [finger]".to_string()),
+        }
+        Ok(())
+    }
+
+    fn regs(&mut self, args: &[&str]) -> Result<(), String> {
+        let [] = optional_args(args, [])?;
+        for (i, val) in self.machine.registers().iter().enumerate() {
+            println!("r{}: {:#010x} {:>10}", i, val, val);
+        }
+        Ok(())
+    }
+
+    fn dump(&mut self, args: &[&str]) -> Result<(), String> {
+        let [array, offset, len] = match args {
+            [array, offset, len] => [number(array)?, number(offset)?, number(len)?],
+            _ => return Err("usage: dump <array> <off> <len>".to_string()),
+        };
+        let Some(platters) = self.machine.array(ArrayId(array)) else {
+            return Err(format!("no array {:#x}", array));
+        };
+        let start = (offset as usize).min(platters.len());
+        let end = start.saturating_add(len as usize).min(platters.len());
+        print!(
+            "{}",
+            HexDump {
+                start,
+                platters: &platters[start..end],
+            }
+        );
+        Ok(())
+    }
+
+    fn disasm(&mut self, args: &[&str]) -> Result<(), String> {
+        let [finger, count] = match args {
+            [] => [self.machine.finger(), 10],
+            _ => {
+                let [count] = optional_args(&args[1..], [10])?;
+                [number(args[0])?, count]
+            }
+        };
+        for finger in finger..finger.saturating_add(count) {
+            if !self.show_instruction(finger) {
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn finger(&mut self, args: &[&str]) -> Result<(), String> {
+        let [] = optional_args(args, [])?;
+        self.show_finger();
+        Ok(())
+    }
+
+    /// Executes one instruction, returning false once the machine has stopped.
+    fn step_once(&mut self) -> bool {
+        if self.stopped.is_none() {
+            let result = self.machine.step();
+            self.stop_on(result);
+        }
+        self.stopped.is_none()
+    }
+
+    /// Stops the debugger's machine if `result` says it has stopped.
+    fn stop_on(&mut self, result: StepResult) {
+        let reason = match result {
+            StepResult::Running => return,
+            StepResult::Halted => ExitReason::Halted,
+            StepResult::RanOffEnd => ExitReason::RanOffEnd,
+            StepResult::Fault(fault) => ExitReason::Faulted(fault),
+            StepResult::Cancelled => ExitReason::Cancelled,
+        };
+        self.stop(reason);
+    }
+
+    fn stop(&mut self, reason: ExitReason) {
+        let reason = self.machine.finish(reason);
+        match &reason {
+            ExitReason::Halted => println!("machine halted"),
+            ExitReason::RanOffEnd => println!("machine failed: ran off the end of the program"),
+            ExitReason::Faulted(fault) => println!("machine failed: {}", fault),
+            ExitReason::Cancelled => println!("machine timed out"),
+        }
+        self.stopped = Some(reason);
+    }
+
+    /// Writes out the program's buffered output before the debugger prints.
+    fn flush(&mut self) {
+        if let Err(fault) = self.machine.flush_console() {
+            println!("machine failed: {}", fault);
+            self.stopped.get_or_insert(ExitReason::Faulted(fault));
+        }
+    }
+
+    fn show_finger(&self) {
+        if self.stopped.is_some() {
+            println!("machine stopped at {:#x}", self.machine.finger());
+        } else {
+            self.show_instruction(self.machine.finger());
+        }
+    }
+
+    /// Prints the platter of array 0 at `finger`, returning false if there
+    /// is none.
+    fn show_instruction(&self, finger: Word) -> bool {
+        let program = self.machine.program();
+        let Some(&platter) = program.get(finger as usize) else {
+            println!("{:#010x}: past the end of the program", finger);
+            return false;
+        };
+        let mark = if self.breakpoints.contains(&finger) {
+            '*'
+        } else {
+            ' '
+        };
+        println!("{}{}", mark, Line { finger, platter });
+        true
+    }
+}
+
+/// Parses up to `N` numeric arguments, filling in the rest from `defaults`.
+fn optional_args<const N: usize>(args: &[&str], defaults: [Word; N]) -> Result<[Word; N], String> {
+    if args.len() > N {
+        return Err(format!("expected at most {} argument(s)", N));
+    }
+    let mut values = defaults;
+    for (value, arg) in values.iter_mut().zip(args) {
+        *value = number(arg)?;
+    }
+    Ok(values)
+}
+
+/// Parses a command's numeric argument.
+fn number(arg: &str) -> Result<Word, String> {
+    parse_number(arg).ok_or_else(|| format!("not a number: `{}`", arg))
+}
@@ -0,0 +1,102 @@
+//! Crash dumps: the state of a machine when it faulted, saved for later
+//! inspection.
+//!
+//! A dump is a sequence of big-endian 32-bit words, like a scroll:
+//!
+//! | field          | words        | contents                                 |
+//! |----------------|--------------|------------------------------------------|
+//! | magic          | 1            | `0x554d4344`, "UMCD" in ASCII            |
+//! | version        | 1            | `1`                                      |
+//! | finger         | 1            | finger of the faulting instruction       |
+//! | registers      | 8            | `r0` to `r7` before it took effect       |
+//! | message length | 1            | length in bytes of the message           |
+//! | message        | ⌈length / 4⌉ | the fault report, UTF-8, zero-padded     |
+//! | array count    | 1            | number of arrays that follow             |
+//! | arrays         |              | one record per array, array 0 first      |
+//!
+//! Each array record is its identifier, its length in platters, then the
+//! platters themselves. Only active arrays are recorded.
+
+use crate::errors::Fault;
+use crate::format::{
+    invalid_data, read_bytes, read_header, read_platters, read_word, write_bytes, write_platters,
+    write_word,
+};
+use crate::instructions::ArrayId;
+use crate::machine::{Machine, Word};
+use std::io::{self, Read, Write};
+
+const MAGIC: Word = u32::from_be_bytes(*b"UMCD");
+const VERSION: Word = 1;
+
+pub struct CrashDump {
+    pub finger: Word,
+    pub registers: [Word; 8],
+    /// The fault as it was reported, see `Fault`'s `Display`.
+    pub message: String,
+    /// Every active array, array 0 first.
+    pub arrays: Vec<(ArrayId, Vec<Word>)>,
+}
+
+impl CrashDump {
+    pub fn capture(machine: &Machine, fault: &Fault) -> CrashDump {
+        let program = (ArrayId(0), machine.program().to_vec());
+        let data_arrays = machine
+            .data_arrays()
+            .iter()
+            .map(|(id, array)| (id, array.to_vec()));
+        CrashDump {
+            finger: fault.finger,
+            registers: fault.registers,
+            message: fault.to_string(),
+            arrays: std::iter::once(program).chain(data_arrays).collect(),
+        }
+    }
+
+    /// Contents of the given array, where array 0 is the program.
+    pub fn array(&self, id: ArrayId) -> Option<&[Word]> {
+        self.arrays
+            .iter()
+            .find(|(array_id, _)| *array_id == id)
+            .map(|(_, array)| &array[..])
+    }
+
+    pub fn write_to<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_word(out, MAGIC)?;
+        write_word(out, VERSION)?;
+        write_word(out, self.finger)?;
+        for &reg in &self.registers {
+            write_word(out, reg)?;
+        }
+        write_bytes(out, self.message.as_bytes())?;
+        write_word(out, self.arrays.len() as Word)?;
+        for (id, array) in &self.arrays {
+            write_word(out, id.0)?;
+            write_platters(out, array)?;
+        }
+        out.flush()
+    }
+
+    pub fn read_from<R: Read>(input: &mut R) -> io::Result<CrashDump> {
+        read_header(input, MAGIC, VERSION, "crash dump")?;
+        let finger = read_word(input)?;
+        let mut registers = [0; 8];
+        for reg in &mut registers {
+            *reg = read_word(input)?;
+        }
+        let message = String::from_utf8(read_bytes(input)?)
+            .map_err(|_| invalid_data("message is not UTF-8"))?;
+        let count = read_word(input)?;
+        let mut arrays = Vec::new();
+        for _ in 0..count {
+            let id = ArrayId(read_word(input)?);
+            arrays.push((id, read_platters(input)?));
+        }
+        Ok(CrashDump {
+            finger,
+            registers,
+            message,
+            arrays,
+        })
+    }
+}
@@ -0,0 +1,140 @@
+use crate::instructions::{ArrayId, Instruction};
+use crate::listing::Line;
+use crate::machine::Word;
+use std::error::Error;
+use std::fmt;
+use std::io;
+
+#[derive(Debug)]
+pub enum UmError {
+    UnknownInstruction { inst: Word },
+    InvalidRegisterIndex { idx: u8 },
+    ProgramOutOfRange,
+    ArrayOutOfRange,
+    InvalidArrayId,
+    ArrayIdsExhausted,
+    DivideByZero,
+    CannotAbandonProgram,
+    InvalidOutput { val: Word },
+    ConsoleFailure { err: io::Error },
+    InstructionLimit { limit: u64 },
+    AllocationTooLarge { size: Word, limit: Word },
+    PlatterQuotaExceeded { limit: u64 },
+    ArrayCountExceeded { limit: usize },
+}
+
+impl fmt::Display for UmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            UmError::UnknownInstruction { inst } => {
+                write!(
+                    f,
+                    "unknown operator {} in platter {:#010x}",
+                    inst >> 28,
+                    inst
+                )
+            }
+            UmError::InvalidRegisterIndex { idx } => write!(f, "there is no register {}", idx),
+            UmError::ProgramOutOfRange => write!(f, "offset is past the end of the program"),
+            UmError::ArrayOutOfRange => write!(f, "offset is past the end of the array"),
+            UmError::InvalidArrayId => write!(f, "array is not active"),
+            UmError::ArrayIdsExhausted => write!(f, "every array identifier is in use"),
+            UmError::DivideByZero => write!(f, "division by zero"),
+            UmError::CannotAbandonProgram => write!(f, "cannot abandon the program (array 0)"),
+            UmError::InvalidOutput { val } => {
+                write!(f, "cannot output {}, which is above 255", val)
+            }
+            UmError::ConsoleFailure { err } => write!(f, "console failed: {}", err),
+            UmError::InstructionLimit { limit } => {
+                write!(f, "ran out of instructions after {}", limit)
+            }
+            UmError::AllocationTooLarge { size, limit } => write!(
+                f,
+                "cannot allocate {} platters at once; the limit is {}",
+                size, limit
+            ),
+            UmError::PlatterQuotaExceeded { limit } => {
+                write!(
+                    f,
+                    "arrays would hold more than the {} platters allowed",
+                    limit
+                )
+            }
+            UmError::ArrayCountExceeded { limit } => {
+                write!(f, "more than the {} arrays allowed would be live", limit)
+            }
+        }
+    }
+}
+
+impl Error for UmError {}
+
+/// An error together with the state of the machine when it was raised.
+#[derive(Debug)]
+pub struct Fault {
+    pub error: UmError,
+    /// Finger of the instruction that failed.
+    pub finger: Word,
+    /// The platter at `finger`, unless that is past the end of the program.
+    pub word: Option<Word>,
+    /// `word` decoded, unless it does not decode.
+    pub instruction: Option<Instruction>,
+    /// Registers before the instruction took effect.
+    pub registers: [Word; 8],
+    /// The array the instruction addresses, if it addresses one.
+    pub array: Option<ArrayContext>,
+}
+
+#[derive(Debug)]
+pub struct ArrayContext {
+    pub id: ArrayId,
+    pub offset: Option<Word>,
+    /// `None` if the array is not active.
+    pub len: Option<usize>,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{}", self.error)?;
+        match self.word {
+            Some(platter) => {
+                let line = Line {
+                    finger: self.finger,
+                    platter,
+                };
+                writeln!(f, "  at finger {}", line)?
+            }
+            None => writeln!(
+                f,
+                "  at finger {:#010x}, past the end of the program",
+                self.finger
+            )?,
+        }
+        if let Some(array) = &self.array {
+            write!(f, "  array {:#x}", array.id.0)?;
+            if let Some(offset) = array.offset {
+                write!(f, ", offset {:#x}", offset)?;
+            }
+            match array.len {
+                Some(len) => writeln!(f, ", length {:#x}", len)?,
+                None => writeln!(f, ", not active")?,
+            }
+        }
+        for (row, registers) in self.registers.chunks(4).enumerate() {
+            write!(f, " ")?;
+            for (i, val) in registers.iter().enumerate() {
+                write!(f, " r{} = {:#010x}", row * 4 + i, val)?;
+            }
+            if row == 0 {
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Error for Fault {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.error)
+    }
+}
@@ -0,0 +1,83 @@
+//! Building blocks of the file formats: everything is stored as big-endian
+//! 32-bit words, like a scroll.
+
+use crate::machine::Word;
+use std::io::{self, Read, Write};
+
+pub fn write_word<W: Write>(out: &mut W, word: Word) -> io::Result<()> {
+    out.write_all(&word.to_be_bytes())
+}
+
+pub fn read_word<R: Read>(input: &mut R) -> io::Result<Word> {
+    let mut bytes = [0; 4];
+    input.read_exact(&mut bytes)?;
+    Ok(Word::from_be_bytes(bytes))
+}
+
+/// Writes a length in platters followed by the platters.
+pub fn write_platters<W: Write>(out: &mut W, platters: &[Word]) -> io::Result<()> {
+    write_word(out, platters.len() as Word)?;
+    for &platter in platters {
+        write_word(out, platter)?;
+    }
+    Ok(())
+}
+
+pub fn read_platters<R: Read>(input: &mut R) -> io::Result<Vec<Word>> {
+    let len = read_word(input)?;
+    (0..len).map(|_| read_word(input)).collect()
+}
+
+/// Writes a length in bytes followed by the bytes, zero-padded to a whole
+/// number of words.
+pub fn write_bytes<W: Write>(out: &mut W, bytes: &[u8]) -> io::Result<()> {
+    write_word(out, bytes.len() as Word)?;
+    out.write_all(bytes)?;
+    out.write_all(&[0; 3][..padding(bytes.len())])
+}
+
+pub fn read_bytes<R: Read>(input: &mut R) -> io::Result<Vec<u8>> {
+    let len = read_word(input)?;
+    // The length may be corrupt, so only what is actually there is
+    // allocated.
+    let mut bytes = Vec::new();
+    input.take(u64::from(len)).read_to_end(&mut bytes)?;
+    if bytes.len() != len as usize {
+        return Err(io::Error::new(
+            io::ErrorKind::UnexpectedEof,
+            "file ends before the bytes it declares",
+        ));
+    }
+    input.read_exact(&mut [0; 3][..padding(bytes.len())])?;
+    Ok(bytes)
+}
+
+/// Reads the magic word and version that start every file, rejecting
+/// anything else.
+pub fn read_header<R: Read>(
+    input: &mut R,
+    magic: Word,
+    version: Word,
+    kind: &str,
+) -> io::Result<()> {
+    if read_word(input)? != magic {
+        return Err(invalid_data(&format!("not a {}", kind)));
+    }
+    let found = read_word(input)?;
+    if found != version {
+        return Err(invalid_data(&format!(
+            "unsupported {} version {}",
+            kind, found
+        )));
+    }
+    Ok(())
+}
+
+pub fn invalid_data(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+/// Bytes needed to pad `len` bytes to a whole number of words.
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
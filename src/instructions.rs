@@ -1,5 +1,6 @@
-use super::errors::UmError;
-use super::machine::Word;
+use crate::errors::UmError;
+use crate::machine::Word;
+use std::fmt;
 use std::marker::PhantomData;
 
 /// Identifies an input register by index.
@@ -11,9 +12,11 @@ pub struct In<T> {
 }
 
 impl<T> In<T> {
-    fn new(idx: u8) -> In<T> {
+    /// Panics if `idx` is not a register, 0 to 7.
+    pub fn new(idx: u8) -> In<T> {
+        assert!(idx < 8, "no register r{}", idx);
         In {
-            idx: idx,
+            idx,
             phantom: PhantomData,
         }
     }
@@ -24,7 +27,9 @@ impl<T> In<T> {
 pub struct Out(pub u8);
 
 impl Out {
-    fn new(idx: u8) -> Out {
+    /// Panics if `idx` is not a register, 0 to 7.
+    pub fn new(idx: u8) -> Out {
+        assert!(idx < 8, "no register r{}", idx);
         Out(idx)
     }
 }
@@ -49,7 +54,26 @@ impl From<u32> for ArrayId {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+/// Mnemonic of each operator, by number, as `Instruction`'s `Display`
+/// writes them.
+pub const MNEMONICS: [&str; 14] = [
+    "cmov",
+    "index",
+    "amend",
+    "add",
+    "mul",
+    "div",
+    "nand",
+    "halt",
+    "alloc",
+    "abandon",
+    "output",
+    "input",
+    "loadprog",
+    "orthography",
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum Instruction {
     ConditionalMove {
         dest: Out,
@@ -228,4 +252,103 @@ impl Instruction {
             _ => Err(UmError::UnknownInstruction { inst: word }),
         }
     }
+
+    fn encode_standard_abc(op_number: Word, abc: Abc) -> Word {
+        op_number << 28
+            | Word::from(abc.a & 7) << 6
+            | Word::from(abc.b & 7) << 3
+            | Word::from(abc.c & 7)
+    }
+
+    /// Encodes the instruction as a platter, the inverse of `decode_from`.
+    /// Unused bits are left clear. Register indices are truncated to 3 bits
+    /// and `LoadRegister` values to 25 bits.
+    pub fn encode(&self) -> Word {
+        let abc = |a: u8, b: u8, c: u8| Abc { a, b, c };
+        match *self {
+            Instruction::ConditionalMove { dest, src, test } => {
+                Instruction::encode_standard_abc(0, abc(dest.0, src.idx, test.idx))
+            }
+            Instruction::ArrayIndex {
+                dest,
+                offset,
+                array,
+            } => Instruction::encode_standard_abc(1, abc(dest.0, array.idx, offset.idx)),
+            Instruction::ArrayAmend { array, offset, val } => {
+                Instruction::encode_standard_abc(2, abc(array.idx, offset.idx, val.idx))
+            }
+            Instruction::Add { dest, x, y } => {
+                Instruction::encode_standard_abc(3, abc(dest.0, x.idx, y.idx))
+            }
+            Instruction::Multiply { dest, x, y } => {
+                Instruction::encode_standard_abc(4, abc(dest.0, x.idx, y.idx))
+            }
+            Instruction::Divide { dest, x, y } => {
+                Instruction::encode_standard_abc(5, abc(dest.0, x.idx, y.idx))
+            }
+            Instruction::Nand { dest, x, y } => {
+                Instruction::encode_standard_abc(6, abc(dest.0, x.idx, y.idx))
+            }
+            Instruction::Halt => 7 << 28,
+            Instruction::Allocate { size, result } => {
+                Instruction::encode_standard_abc(8, abc(0, result.0, size.idx))
+            }
+            Instruction::Abandon { which } => {
+                Instruction::encode_standard_abc(9, abc(0, 0, which.idx))
+            }
+            Instruction::Output { val } => Instruction::encode_standard_abc(10, abc(0, 0, val.idx)),
+            Instruction::Input { dest } => Instruction::encode_standard_abc(11, abc(0, 0, dest.0)),
+            Instruction::LoadProgram { from, finger } => {
+                Instruction::encode_standard_abc(12, abc(0, from.idx, finger.idx))
+            }
+            Instruction::LoadRegister { dest, val } => {
+                13 << 28 | Word::from(dest.0 & 7) << 25 | (val & ((1 << 25) - 1))
+            }
+        }
+    }
+}
+
+/// Formats an instruction as a mnemonic followed by its operands, e.g.
+/// `add r1 <- r2, r3` or `orthography r3 <- 0x1ffffff`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Instruction::ConditionalMove { dest, src, test } => {
+                write!(f, "cmov r{} <- r{} if r{}", dest.0, src.idx, test.idx)
+            }
+            Instruction::ArrayIndex {
+                dest,
+                offset,
+                array,
+            } => write!(f, "index r{} <- r{}[r{}]", dest.0, array.idx, offset.idx),
+            Instruction::ArrayAmend { array, offset, val } => {
+                write!(f, "amend r{}[r{}] <- r{}", array.idx, offset.idx, val.idx)
+            }
+            Instruction::Add { dest, x, y } => {
+                write!(f, "add r{} <- r{}, r{}", dest.0, x.idx, y.idx)
+            }
+            Instruction::Multiply { dest, x, y } => {
+                write!(f, "mul r{} <- r{}, r{}", dest.0, x.idx, y.idx)
+            }
+            Instruction::Divide { dest, x, y } => {
+                write!(f, "div r{} <- r{}, r{}", dest.0, x.idx, y.idx)
+            }
+            Instruction::Nand { dest, x, y } => {
+                write!(f, "nand r{} <- r{}, r{}", dest.0, x.idx, y.idx)
+            }
+            Instruction::Halt => write!(f, "halt"),
+            Instruction::Allocate { size, result } => {
+                write!(f, "alloc r{} <- r{}", result.0, size.idx)
+            }
+            Instruction::Abandon { which } => write!(f, "abandon r{}", which.idx),
+            Instruction::Output { val } => write!(f, "output r{}", val.idx),
+            Instruction::Input { dest } => write!(f, "input r{}", dest.0),
+            Instruction::LoadProgram { from, finger } => {
+                write!(f, "loadprog r{}, r{}", from.idx, finger.idx)
+            }
+            Instruction::LoadRegister { dest, val } => {
+                write!(f, "orthography r{} <- {:#x}", dest.0, val)
+            }
+        }
+    }
 }
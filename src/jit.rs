@@ -0,0 +1,604 @@
+//! Translates straight-line runs of instructions in array 0 into x86-64 code.
+//!
+//! A block starts at whatever finger the machine jumps to and extends up to
+//! and including the first `LoadProgram`, or until the first `Halt`, `Output`,
+//! `Input`, undecodable platter or the end of the program. Those are always
+//! left to the interpreter, as is a `LoadProgram` from an array other than 0.
+//! Register arithmetic and jumps within array 0 are emitted inline; array
+//! operations call back into the machine through a helper so that they share
+//! the interpreter's semantics exactly.
+//!
+//! Compiled blocks operate directly on the machine's register file:
+//!
+//! ```text
+//! extern "sysv64" fn block(
+//!     registers: *mut Word,
+//!     context: *mut c_void,
+//!     entries: *const usize,
+//!     num_entries: usize,
+//! ) -> u64
+//! ```
+//!
+//! `entries` holds, for each finger, the address just past the prologue of
+//! the block starting there, or 0. A jump within array 0 to a finger that
+//! already has a block continues there directly without returning.
+//!
+//! If the machine has a cancellation flag, every jump checks it first and
+//! returns instead of continuing while it is set, so that a program looping
+//! in native code can still be stopped.
+//!
+//! If the machine keeps statistics, each instruction adds to its counters
+//! once it has completed, so that native code is counted exactly as the
+//! interpreter would count it. An instruction that faults is left for the
+//! interpreter to count when it re-executes it.
+//!
+//! The low 32 bits of the result hold the finger to resume at. Bit 32 is set
+//! when the instruction at that finger must be executed by the interpreter
+//! before re-entering native code, which is how faults are reported: the
+//! interpreter re-executes the offending instruction and raises the error.
+
+#[cfg(not(all(target_arch = "x86_64", target_os = "linux")))]
+compile_error!("the `jit` feature is only supported on x86-64 Linux");
+
+use crate::instructions::{In, Instruction, Out};
+use crate::machine::Word;
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::ptr;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+/// Called for instructions that are not translated inline. Receives the
+/// context pointer handed to the block and the instruction's platter.
+/// Returns `HELPER_CONTINUE`, `HELPER_EXIT` or `HELPER_FAULT`.
+pub type Helper = extern "sysv64" fn(*mut c_void, Word) -> u32;
+
+/// The helper called for each kind of instruction that touches arrays.
+#[derive(Copy, Clone)]
+pub struct Helpers {
+    pub array_index: Helper,
+    pub array_amend: Helper,
+    pub allocate: Helper,
+    pub abandon: Helper,
+}
+
+/// The instruction completed; keep running the block.
+pub const HELPER_CONTINUE: u32 = 0;
+/// The instruction completed but invalidated compiled code; leave the block.
+pub const HELPER_EXIT: u32 = 1;
+/// The instruction failed; leave the block without executing it.
+pub const HELPER_FAULT: u32 = 2;
+
+/// Longest run of instructions translated as a single block.
+const MAX_BLOCK_LEN: usize = 256;
+
+/// Size of the executable mapping. Filling it discards every block.
+const ARENA_SIZE: usize = 16 << 20;
+
+const INTERPRET_FLAG: u64 = 1 << 32;
+
+type BlockFn = unsafe extern "sysv64" fn(*mut Word, *mut c_void, *const usize, usize) -> u64;
+
+#[derive(Copy, Clone)]
+enum Slot {
+    /// No attempt has been made to compile a block here.
+    Unknown,
+    /// The instruction here must go through the interpreter.
+    Interpret,
+    /// A compiled block starting here and ending before `end`.
+    Native { entry: usize, end: usize },
+}
+
+/// Counters that compiled code adds to, all within one allocation that
+/// outlives the code.
+#[derive(Copy, Clone)]
+pub struct Counters {
+    /// Instructions executed.
+    pub instructions: *mut u64,
+    /// Instructions executed per operator, 14 counters in a row.
+    pub operators: *mut u64,
+    /// `LoadProgram`s that jumped within array 0.
+    pub loads_in_place: *mut u64,
+}
+
+impl Counters {
+    /// Offset of `counter` from `instructions`, for addressing it relative
+    /// to that.
+    fn displacement(&self, counter: *mut u64) -> i32 {
+        let offset = counter as isize - self.instructions as isize;
+        i32::try_from(offset).expect("counters must be close together")
+    }
+}
+
+/// Where to resume after a block returns.
+pub struct Exit {
+    pub finger: Word,
+    /// Whether the instruction at `finger` must be interpreted next.
+    pub interpret: bool,
+}
+
+/// An entry point into compiled code.
+#[derive(Copy, Clone)]
+pub struct Block {
+    code: BlockFn,
+    entries: *const usize,
+    num_entries: usize,
+}
+
+impl Block {
+    /// Runs the block.
+    ///
+    /// # Safety
+    ///
+    /// `registers` must point at the eight registers of the machine whose
+    /// program the block was compiled from, and `context` must be what the
+    /// block's helpers expect. The `Jit` must not be reset or grown while
+    /// the block runs.
+    pub unsafe fn call(self, registers: *mut Word, context: *mut c_void) -> Exit {
+        let result = (self.code)(registers, context, self.entries, self.num_entries);
+        Exit {
+            finger: result as Word,
+            interpret: result & INTERPRET_FLAG != 0,
+        }
+    }
+}
+
+pub struct Jit {
+    arena: Arena,
+    /// One entry per platter of the program, grown on demand.
+    slots: Vec<Slot>,
+    /// Address of the body of each `Slot::Native` block, otherwise 0.
+    entries: Vec<usize>,
+    /// Whether any block covers each platter of the program.
+    covered: Vec<bool>,
+    /// Set when a write to array 0 discarded at least one block.
+    dirty: bool,
+    /// Checked at every jump; kept here so that its address stays valid.
+    cancel: Option<Arc<AtomicBool>>,
+    counters: Option<Counters>,
+}
+
+// The arena belongs to the `Jit` alone, and the counters and the
+// cancellation flag stay valid wherever the machine goes, so moving it to
+// another thread is sound.
+unsafe impl Send for Jit {}
+
+impl Jit {
+    pub fn new() -> Jit {
+        Jit {
+            arena: Arena::new(ARENA_SIZE),
+            slots: Vec::new(),
+            entries: Vec::new(),
+            covered: Vec::new(),
+            dirty: false,
+            cancel: None,
+            counters: None,
+        }
+    }
+
+    /// Makes compiled code leave at the next jump while `cancel` is set.
+    /// Discards all compiled code. Must not be called while a block runs.
+    pub fn set_cancel(&mut self, cancel: Option<Arc<AtomicBool>>) {
+        self.cancel = cancel;
+        self.reset();
+    }
+
+    /// Makes compiled code count what it executes in `counters`, which must
+    /// stay valid until replaced. Discards all compiled code. Must not be
+    /// called while a block runs.
+    pub fn set_counters(&mut self, counters: Option<Counters>) {
+        self.counters = counters;
+        self.reset();
+    }
+
+    /// Returns the block starting at `finger`, compiling it on first use.
+    /// Returns `None` if the instruction there must be interpreted.
+    pub fn block_at(&mut self, finger: Word, program: &[Word], helpers: Helpers) -> Option<Block> {
+        let start = finger as usize;
+        if start >= program.len() {
+            return None;
+        }
+        if self.slots.len() < program.len() {
+            self.grow(program.len());
+        }
+        self.dirty = false;
+        match self.slots[start] {
+            Slot::Native { entry, .. } => Some(self.block(entry)),
+            Slot::Interpret => None,
+            Slot::Unknown => self.compile(start, program, helpers),
+        }
+    }
+
+    /// Discards every block covering `offset`, which has just been amended.
+    pub fn invalidate(&mut self, offset: usize) {
+        match self.slots.get(offset) {
+            Some(Slot::Interpret) => self.slots[offset] = Slot::Unknown,
+            Some(_) => {}
+            None => return,
+        }
+        if !self.covered[offset] {
+            return;
+        }
+        self.dirty = true;
+        let lo = (offset + 1).saturating_sub(MAX_BLOCK_LEN);
+        for start in lo..=offset {
+            if let Slot::Native { end, .. } = self.slots[start] {
+                if end > offset {
+                    self.slots[start] = Slot::Unknown;
+                    self.entries[start] = 0;
+                }
+            }
+        }
+        // Recompute coverage around the discarded blocks from the survivors.
+        let hi = (offset + MAX_BLOCK_LEN).min(self.covered.len());
+        for covered in &mut self.covered[lo..hi] {
+            *covered = false;
+        }
+        for start in lo.saturating_sub(MAX_BLOCK_LEN)..hi {
+            if let Slot::Native { end, .. } = self.slots[start] {
+                for covered in &mut self.covered[start.max(lo)..end.min(hi)] {
+                    *covered = true;
+                }
+            }
+        }
+    }
+
+    /// Whether compiled code was discarded since the current block started.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    /// Discards all compiled code. Must not be called while a block runs.
+    pub fn reset(&mut self) {
+        self.slots.clear();
+        self.entries.clear();
+        self.covered.clear();
+        self.arena.clear();
+        self.dirty = false;
+    }
+
+    fn compile(&mut self, start: usize, program: &[Word], helpers: Helpers) -> Option<Block> {
+        let mut end = start;
+        let mut jumps = false;
+        while !jumps && end < program.len() && end - start < MAX_BLOCK_LEN {
+            match Instruction::decode_from(program[end]) {
+                Ok(Instruction::LoadProgram { .. }) => {
+                    end += 1;
+                    jumps = true;
+                }
+                Ok(inst) if is_translatable(inst) => end += 1,
+                _ => break,
+            }
+        }
+        if end == start {
+            self.slots[start] = Slot::Interpret;
+            return None;
+        }
+
+        let mut asm = Assembler {
+            code: Vec::new(),
+            cancel: self.cancel.as_ref().map(|cancel| cancel.as_ptr() as usize),
+            counters: self.counters,
+        };
+        asm.prologue();
+        for (finger, &word) in program.iter().enumerate().take(end).skip(start) {
+            // Checked by the scan above.
+            let inst = Instruction::decode_from(word).ok()?;
+            asm.instruction(inst, word, finger as Word, helpers);
+        }
+        if !jumps {
+            asm.exit(end as Word);
+        }
+
+        if !self.arena.fits(asm.code.len()) {
+            self.reset();
+            self.grow(program.len());
+        }
+        let entry = self.arena.push(&asm.code);
+        self.slots[start] = Slot::Native { entry, end };
+        self.entries[start] = self.arena.address(entry + PROLOGUE_LEN);
+        for covered in &mut self.covered[start..end] {
+            *covered = true;
+        }
+        Some(self.block(entry))
+    }
+
+    fn grow(&mut self, len: usize) {
+        self.slots.resize(len, Slot::Unknown);
+        self.entries.resize(len, 0);
+        self.covered.resize(len, false);
+    }
+
+    fn block(&self, entry: usize) -> Block {
+        Block {
+            code: unsafe { std::mem::transmute::<usize, BlockFn>(self.arena.address(entry)) },
+            entries: self.entries.as_ptr(),
+            num_entries: self.entries.len(),
+        }
+    }
+}
+
+fn is_translatable(inst: Instruction) -> bool {
+    !matches!(
+        inst,
+        Instruction::Halt | Instruction::Output { .. } | Instruction::Input { .. }
+    )
+}
+
+/// Emits machine code. `rbx` holds the register file, `r12` the context,
+/// `r13` the entry table and `rbp` its length.
+struct Assembler {
+    code: Vec<u8>,
+    /// Address of the cancellation flag, if there is one.
+    cancel: Option<usize>,
+    counters: Option<Counters>,
+}
+
+impl Assembler {
+    fn bytes(&mut self, bytes: &[u8]) {
+        self.code.extend_from_slice(bytes);
+    }
+
+    fn imm32(&mut self, val: u32) {
+        self.bytes(&val.to_le_bytes());
+    }
+
+    fn imm64(&mut self, val: u64) {
+        self.bytes(&val.to_le_bytes());
+    }
+
+    /// Always `PROLOGUE_LEN` bytes long.
+    fn prologue(&mut self) {
+        // push rbx; push r12; push r13; push rbp; sub rsp, 8
+        self.bytes(&[0x53, 0x41, 0x54, 0x41, 0x55, 0x55, 0x48, 0x83, 0xec, 0x08]);
+        // mov rbx, rdi; mov r12, rsi; mov r13, rdx; mov rbp, rcx
+        self.bytes(&[0x48, 0x89, 0xfb, 0x49, 0x89, 0xf4]);
+        self.bytes(&[0x49, 0x89, 0xd5, 0x48, 0x89, 0xcd]);
+    }
+
+    /// Returns whatever is in `rax`. Always `EPILOGUE_LEN` bytes long.
+    fn epilogue(&mut self) {
+        // add rsp, 8; pop rbp; pop r13; pop r12; pop rbx; ret
+        self.bytes(&[
+            0x48, 0x83, 0xc4, 0x08, 0x5d, 0x41, 0x5d, 0x41, 0x5c, 0x5b, 0xc3,
+        ]);
+    }
+
+    /// Returns `result` to the caller. Always `EXIT_LEN` bytes long.
+    fn ret(&mut self, result: u64) {
+        // mov rax, imm64
+        self.bytes(&[0x48, 0xb8]);
+        self.imm64(result);
+        self.epilogue();
+    }
+
+    fn exit(&mut self, finger: Word) {
+        self.ret(u64::from(finger));
+    }
+
+    fn fault(&mut self, finger: Word) {
+        self.ret(INTERPRET_FLAG | u64::from(finger));
+    }
+
+    /// `op eax/ecx, [rbx + 4 * idx]` for a one-byte opcode, or the register
+    /// store when `opcode` is 0x89.
+    fn reg_op(&mut self, opcode: &[u8], reg: u8, idx: u8) {
+        self.bytes(opcode);
+        self.bytes(&[0x43 | (reg << 3), idx * 4]);
+    }
+
+    fn load_eax<T>(&mut self, src: In<T>) {
+        self.reg_op(&[0x8b], 0, src.idx);
+    }
+
+    fn store_eax(&mut self, dest: Out) {
+        self.reg_op(&[0x89], 0, dest.0);
+    }
+
+    /// Code counting a completed instruction with platter `word`, and a jump
+    /// within array 0 if `in_place`. Empty when not counting. Only
+    /// clobbers `rdx` and the flags.
+    fn counting(&self, word: Word, in_place: bool) -> Vec<u8> {
+        let Some(counters) = self.counters else {
+            return Vec::new();
+        };
+        // mov rdx, instructions; add qword [rdx], 1
+        let mut code = vec![0x48, 0xba];
+        code.extend_from_slice(&(counters.instructions as u64).to_le_bytes());
+        code.extend_from_slice(&[0x48, 0x83, 0x02, 0x01]);
+        let operator = counters.operators.wrapping_add((word >> 28) as usize);
+        let mut counted = vec![operator];
+        if in_place {
+            counted.push(counters.loads_in_place);
+        }
+        for counter in counted {
+            // add qword [rdx + disp32], 1
+            code.extend_from_slice(&[0x48, 0x83, 0x82]);
+            code.extend_from_slice(&counters.displacement(counter).to_le_bytes());
+            code.push(0x01);
+        }
+        code
+    }
+
+    /// Continues at the finger held in the given register: directly if a
+    /// block starts there, otherwise by returning it to the caller.
+    fn jump_register<T>(&mut self, src: In<T>) {
+        self.load_eax(src);
+        if let Some(cancel) = self.cancel {
+            // mov rdx, cancel; cmp byte [rdx], 0; jne to the epilogue
+            self.bytes(&[0x48, 0xba]);
+            self.imm64(cancel as u64);
+            self.bytes(&[0x80, 0x3a, 0x00, 0x75, 3 + 2 + 5 + 3 + 2 + 2]);
+        }
+        // cmp rax, rbp; jae to the epilogue
+        self.bytes(&[0x48, 0x39, 0xe8, 0x73, 5 + 3 + 2 + 2]);
+        // mov rdx, [r13 + 8 * rax]; test rdx, rdx; jz to the epilogue
+        self.bytes(&[0x49, 0x8b, 0x54, 0xc5, 0x00, 0x48, 0x85, 0xd2, 0x74, 2]);
+        // jmp rdx
+        self.bytes(&[0xff, 0xe2]);
+        self.epilogue();
+    }
+
+    fn instruction(&mut self, inst: Instruction, word: Word, finger: Word, helpers: Helpers) {
+        let counting = self.counting(word, false);
+        match inst {
+            Instruction::ConditionalMove { dest, src, test } => {
+                self.load_eax(test);
+                // test eax, eax; jz over the move
+                self.bytes(&[0x85, 0xc0, 0x74, 6]);
+                self.load_eax(src);
+                self.store_eax(dest);
+            }
+            Instruction::Add { dest, x, y } => {
+                self.load_eax(x);
+                self.reg_op(&[0x03], 0, y.idx);
+                self.store_eax(dest);
+            }
+            Instruction::Multiply { dest, x, y } => {
+                self.load_eax(x);
+                self.reg_op(&[0x0f, 0xaf], 0, y.idx);
+                self.store_eax(dest);
+            }
+            Instruction::Divide { dest, x, y } => {
+                // mov ecx, y; test ecx, ecx; jnz over the fault exit
+                self.reg_op(&[0x8b], 1, y.idx);
+                self.bytes(&[0x85, 0xc9, 0x75, EXIT_LEN]);
+                self.fault(finger);
+                self.load_eax(x);
+                // xor edx, edx; div ecx
+                self.bytes(&[0x31, 0xd2, 0xf7, 0xf1]);
+                self.store_eax(dest);
+            }
+            Instruction::Nand { dest, x, y } => {
+                self.load_eax(x);
+                self.reg_op(&[0x23], 0, y.idx);
+                // not eax
+                self.bytes(&[0xf7, 0xd0]);
+                self.store_eax(dest);
+            }
+            Instruction::LoadRegister { dest, val } => {
+                // mov dword [rbx + 4 * dest], imm32
+                self.bytes(&[0xc7, 0x43, dest.0 * 4]);
+                self.imm32(val);
+            }
+            Instruction::LoadProgram { from, finger: to } => {
+                // Only jumps within array 0 stay native.
+                self.load_eax(from);
+                self.bytes(&[0x85, 0xc0, 0x74, EXIT_LEN]);
+                self.fault(finger);
+                let counting = self.counting(word, true);
+                self.bytes(&counting);
+                self.jump_register(to);
+                return;
+            }
+            Instruction::ArrayIndex { .. } => self.call_helper(word, finger, helpers.array_index),
+            Instruction::ArrayAmend { .. } => self.call_helper(word, finger, helpers.array_amend),
+            Instruction::Allocate { .. } => self.call_helper(word, finger, helpers.allocate),
+            Instruction::Abandon { .. } => self.call_helper(word, finger, helpers.abandon),
+            Instruction::Halt | Instruction::Output { .. } | Instruction::Input { .. } => {
+                unreachable!("{:?} is never translated", inst)
+            }
+        }
+        self.bytes(&counting);
+    }
+
+    /// Calls `helper`, leaving the count of a completed instruction to the
+    /// code that follows unless the helper asks to exit.
+    fn call_helper(&mut self, word: Word, finger: Word, helper: Helper) {
+        let counting = self.counting(word, false);
+        let counting_len = counting.len() as u8;
+        // mov rdi, r12; mov esi, word
+        self.bytes(&[0x4c, 0x89, 0xe7, 0xbe]);
+        self.imm32(word);
+        // mov rax, helper; call rax
+        self.bytes(&[0x48, 0xb8]);
+        self.imm64(helper as usize as u64);
+        self.bytes(&[0xff, 0xd0]);
+        // test eax, eax; jz over both exits
+        self.bytes(&[0x85, 0xc0, 0x74, 3 + 2 + counting_len + 2 * EXIT_LEN]);
+        // cmp eax, HELPER_EXIT; jne over the first exit
+        self.bytes(&[0x83, 0xf8, HELPER_EXIT as u8, 0x75, counting_len + EXIT_LEN]);
+        self.bytes(&counting);
+        self.exit(finger + 1);
+        self.fault(finger);
+    }
+}
+
+/// Lengths in bytes of the code emitted by the corresponding `Assembler`
+/// methods.
+const PROLOGUE_LEN: usize = 22;
+const EPILOGUE_LEN: u8 = 11;
+const EXIT_LEN: u8 = 10 + EPILOGUE_LEN;
+
+/// A fixed-size region of readable, writable and executable memory.
+struct Arena {
+    base: *mut u8,
+    size: usize,
+    used: usize,
+}
+
+const PROT_READ: i32 = 1;
+const PROT_WRITE: i32 = 2;
+const PROT_EXEC: i32 = 4;
+const MAP_PRIVATE: i32 = 2;
+const MAP_ANONYMOUS: i32 = 0x20;
+
+extern "C" {
+    fn mmap(addr: *mut c_void, len: usize, prot: i32, flags: i32, fd: i32, off: i64)
+        -> *mut c_void;
+    fn munmap(addr: *mut c_void, len: usize) -> i32;
+}
+
+impl Arena {
+    fn new(size: usize) -> Arena {
+        let base = unsafe {
+            mmap(
+                ptr::null_mut(),
+                size,
+                PROT_READ | PROT_WRITE | PROT_EXEC,
+                MAP_PRIVATE | MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        assert!(
+            base as isize != -1,
+            "Unable to map executable memory for the JIT"
+        );
+        Arena {
+            base: base as *mut u8,
+            size,
+            used: 0,
+        }
+    }
+
+    fn fits(&self, len: usize) -> bool {
+        self.used + len <= self.size
+    }
+
+    fn push(&mut self, code: &[u8]) -> usize {
+        assert!(self.fits(code.len()));
+        let entry = self.used;
+        unsafe {
+            ptr::copy_nonoverlapping(code.as_ptr(), self.base.add(entry), code.len());
+        }
+        self.used += code.len();
+        entry
+    }
+
+    fn address(&self, offset: usize) -> usize {
+        self.base as usize + offset
+    }
+
+    fn clear(&mut self) {
+        self.used = 0;
+    }
+}
+
+impl Drop for Arena {
+    fn drop(&mut self) {
+        unsafe {
+            munmap(self.base as *mut c_void, self.size);
+        }
+    }
+}
@@ -0,0 +1,24 @@
+//! A Universal Machine, as specified for the 2006 ICFP programming contest.
+//!
+//! `machine::Machine` loads a scroll and runs it, either to completion with
+//! `execute` or a step at a time. `instructions` decodes platters and
+//! `errors` describes the ways a program can fail.
+
+pub mod analysis;
+pub mod arrays;
+pub mod asm;
+pub mod console;
+pub mod dump;
+pub mod errors;
+mod format;
+pub mod instructions;
+#[cfg(feature = "jit")]
+mod jit;
+pub mod limits;
+pub mod listing;
+pub mod machine;
+pub mod profile;
+pub mod replay;
+pub mod script;
+pub mod stats;
+pub mod trace;
@@ -0,0 +1,47 @@
+//! Caps on what a program may use, for running programs that are not
+//! trusted.
+
+use crate::arrays::ArrayStore;
+use crate::errors::UmError;
+use crate::machine::Word;
+
+/// Limits on a machine's resources. `None` leaves a resource unlimited,
+/// which is the default for all of them.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Limits {
+    /// Instructions the machine may execute.
+    pub instructions: Option<u64>,
+    /// Platters in all live arrays other than array 0.
+    pub platters: Option<u64>,
+    /// Live arrays other than array 0.
+    pub arrays: Option<usize>,
+    /// Platters in a single `Allocate`.
+    pub allocation: Option<Word>,
+}
+
+impl Limits {
+    /// Checks that another instruction may run after `executed`.
+    pub(crate) fn check_instructions(&self, executed: u64) -> Result<(), UmError> {
+        match self.instructions {
+            Some(limit) if executed >= limit => Err(UmError::InstructionLimit { limit }),
+            _ => Ok(()),
+        }
+    }
+
+    /// Checks that `arrays` has room for another array of `size` platters.
+    pub(crate) fn check_allocation(&self, size: Word, arrays: &ArrayStore) -> Result<(), UmError> {
+        if let Some(limit) = self.allocation.filter(|&limit| size > limit) {
+            return Err(UmError::AllocationTooLarge { size, limit });
+        }
+        if let Some(limit) = self.arrays.filter(|&limit| arrays.len() >= limit) {
+            return Err(UmError::ArrayCountExceeded { limit });
+        }
+        if let Some(limit) = self
+            .platters
+            .filter(|&limit| arrays.platters() + u64::from(size) > limit)
+        {
+            return Err(UmError::PlatterQuotaExceeded { limit });
+        }
+        Ok(())
+    }
+}
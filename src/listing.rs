@@ -0,0 +1,54 @@
+//! Text forms shared by the tools that show a machine to people: the
+//! debugger, fault reports and `um-inspect`.
+
+use crate::instructions::Instruction;
+use crate::machine::Word;
+use std::convert::TryFrom;
+use std::fmt;
+
+/// Parses a decimal or `0x`-prefixed hex number, failing if it does not fit
+/// in a `T`.
+pub fn parse_number<T: TryFrom<u64>>(arg: &str) -> Option<T> {
+    let parsed = match arg.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => arg.parse(),
+    };
+    parsed.ok().and_then(|number| T::try_from(number).ok())
+}
+
+/// A platter of array 0 as its finger, its hex value and the instruction it
+/// decodes to, if any.
+pub struct Line {
+    pub finger: Word,
+    pub platter: Word,
+}
+
+impl fmt::Display for Line {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#010x}: {:08x}", self.finger, self.platter)?;
+        match Instruction::decode_from(self.platter) {
+            Ok(inst) => write!(f, "  {}", inst),
+            Err(_) => Ok(()),
+        }
+    }
+}
+
+/// Platters four to a row, each row led by the offset of its first platter
+/// and ended by a newline.
+pub struct HexDump<'a> {
+    pub start: usize,
+    pub platters: &'a [Word],
+}
+
+impl fmt::Display for HexDump<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for (row, chunk) in self.platters.chunks(4).enumerate() {
+            write!(f, "{:#010x}:", self.start + row * 4)?;
+            for platter in chunk {
+                write!(f, " {:08x}", platter)?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
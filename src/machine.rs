@@ -0,0 +1,953 @@
+use crate::arrays::ArrayStore;
+use crate::console::{Console, ResumedConsole, StdioConsole};
+use crate::errors;
+use crate::format::{
+    invalid_data, read_bytes, read_header, read_platters, read_word, write_bytes, write_platters,
+    write_word,
+};
+use crate::instructions;
+#[cfg(feature = "jit")]
+use crate::jit;
+use crate::limits::Limits;
+use crate::profile::Profiler;
+use crate::stats::Stats;
+use crate::trace::Tracer;
+#[cfg(feature = "jit")]
+use std::ffi::c_void;
+use std::io::{self, Read, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// A platter in the universal machine; a unit of storage.
+pub type Word = u32;
+
+pub struct Machine {
+    finger: Word,
+    registers: [Word; 8],
+    /// Arrays are shared between the program and the data arrays until one
+    /// side writes to them, so `LoadProgram` never has to copy up front.
+    program: Arc<[Word]>,
+    /// Decoded form of each platter of the program, filled in lazily as the
+    /// finger reaches it. Entries are reset whenever array 0 changes.
+    /// May be shorter than the program; missing entries are undecoded.
+    decoded: Vec<Option<instructions::Instruction>>,
+    data_arrays: ArrayStore,
+    console: Box<dyn Console>,
+    /// Instructions executed by `step`, which is all of them unless the JIT
+    /// ran some natively.
+    instructions: u64,
+    tracer: Option<Tracer>,
+    profiler: Option<Profiler>,
+    /// Boxed so that compiled code can count into it wherever the machine
+    /// moves.
+    stats: Option<Box<Stats>>,
+    limits: Limits,
+    cancel: Option<CancelToken>,
+    /// Native code compiled from array 0. Must be told about every write to
+    /// array 0 and every program swap, like `decoded`.
+    #[cfg(feature = "jit")]
+    jit: jit::Jit,
+}
+
+enum Continue {
+    Yes,
+    No,
+}
+
+/// Instructions interpreted between checks for cancellation.
+const CANCEL_INTERVAL: u64 = 1 << 16;
+
+const SNAPSHOT_MAGIC: Word = u32::from_be_bytes(*b"UMSS");
+const SNAPSHOT_VERSION: Word = 2;
+
+/// State of the machine after one or more steps.
+#[derive(Debug)]
+pub enum StepResult {
+    Running,
+    /// Stopped by `Halt`.
+    Halted,
+    /// Stopped because the finger is past the end of the program.
+    RanOffEnd,
+    Fault(errors::Fault),
+    /// Stopped between instructions by a `CancelToken`. `step` checks for
+    /// this only before an `Input`; `run_for` and `run_until` check every
+    /// so often as well.
+    Cancelled,
+}
+
+/// Why `execute` stopped.
+#[derive(Debug)]
+pub enum ExitReason {
+    /// The program executed `Halt`.
+    Halted,
+    /// The finger moved past the end of the program, which the spec counts
+    /// as a failure.
+    RanOffEnd,
+    Faulted(errors::Fault),
+    /// Stopped between instructions by a `CancelToken`, leaving the machine
+    /// ready to snapshot or to run on.
+    Cancelled,
+}
+
+/// Asks a running machine to stop, from any thread.
+///
+/// The machine checks the token every so many instructions, and at every
+/// jump in native code, so it stops soon after `cancel` but not at once. It
+/// also checks before every `Input`, which might wait indefinitely.
+/// Cancelling lasts until `reset`: a machine will not run again with the
+/// same token until then.
+#[derive(Debug, Clone, Default)]
+pub struct CancelToken {
+    cancelled: Arc<AtomicBool>,
+}
+
+impl CancelToken {
+    pub fn new() -> CancelToken {
+        CancelToken::default()
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled.load(Ordering::Relaxed)
+    }
+
+    /// Lets machines with this token run again.
+    pub fn reset(&self) {
+        self.cancelled.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Machine {
+    /// Creates a machine attached to stdin and stdout.
+    pub fn new(program: Vec<u8>) -> Machine {
+        Machine::with_console(program, Box::new(StdioConsole::new()))
+    }
+
+    pub fn with_console(program: Vec<u8>, console: Box<dyn Console>) -> Machine {
+        Machine {
+            finger: 0,
+            registers: [0; 8],
+            program: Arc::from(Machine::load_program_from_bytes(program)),
+            decoded: Vec::new(),
+            data_arrays: ArrayStore::new(),
+            console,
+            instructions: 0,
+            tracer: None,
+            profiler: None,
+            stats: None,
+            limits: Limits::default(),
+            cancel: None,
+            #[cfg(feature = "jit")]
+            jit: jit::Jit::new(),
+        }
+    }
+
+    pub fn load_program_from_bytes(program_bytes: Vec<u8>) -> Vec<Word> {
+        let num_words = if program_bytes.len().is_multiple_of(4) {
+            program_bytes.len() / 4
+        } else {
+            program_bytes.len() / 4 + 1
+        };
+        let mut program = Vec::with_capacity(num_words);
+        for i in 0..num_words {
+            let a: u8 = program_bytes[i * 4];
+            let mut b: u8 = 0;
+            let mut c: u8 = 0;
+            let mut d: u8 = 0;
+            if i * 4 + 1 < program_bytes.len() {
+                b = program_bytes[i * 4 + 1];
+            }
+            if i * 4 + 2 < program_bytes.len() {
+                c = program_bytes[i * 4 + 2];
+            }
+            if i * 4 + 3 < program_bytes.len() {
+                d = program_bytes[i * 4 + 3];
+            }
+            let mut word: Word = u32::from(d);
+            word += u32::from(c) << 8;
+            word += u32::from(b) << 16;
+            word += u32::from(a) << 24;
+            program.push(word)
+        }
+        program
+    }
+
+    #[inline]
+    fn fetch_instruction(&mut self) -> Option<Result<instructions::Instruction, errors::UmError>> {
+        let idx = self.finger as usize;
+        // Anything decoded is still in the program.
+        if let Some(Some(inst)) = self.decoded.get(idx) {
+            self.finger += 1;
+            return Some(Ok(*inst));
+        }
+        if idx >= self.program.len() {
+            None
+        } else {
+            self.finger += 1;
+            let inst = instructions::Instruction::decode_from(self.program[idx]);
+            if let Ok(inst) = inst {
+                if idx >= self.decoded.len() {
+                    self.decoded.resize(idx + 1, None);
+                }
+                self.decoded[idx] = Some(inst);
+            }
+            Some(inst)
+        }
+    }
+
+    fn read_register<T: From<Word>>(&self, reg: instructions::In<T>) -> Result<T, errors::UmError> {
+        if reg.idx >= 8 {
+            Err(errors::UmError::InvalidRegisterIndex { idx: reg.idx })
+        } else {
+            Ok(T::from(self.registers[reg.idx as usize]))
+        }
+    }
+
+    fn set_register(&mut self, reg: instructions::Out, val: Word) -> Result<(), errors::UmError> {
+        if reg.0 >= 8 {
+            Err(errors::UmError::InvalidRegisterIndex { idx: reg.0 })
+        } else {
+            self.registers[reg.0 as usize] = val;
+            Ok(())
+        }
+    }
+
+    fn read_array(
+        &self,
+        array_id: instructions::ArrayId,
+        offset: instructions::Offset,
+    ) -> Result<Word, errors::UmError> {
+        if array_id.0 == 0 {
+            if (offset.0 as usize) < self.program.len() {
+                Ok(self.program[offset.0 as usize])
+            } else {
+                Err(errors::UmError::ProgramOutOfRange)
+            }
+        } else {
+            match self.data_arrays.get(array_id) {
+                Some(array) => {
+                    if (offset.0 as usize) < array.len() {
+                        Ok(array[offset.0 as usize])
+                    } else {
+                        Err(errors::UmError::ArrayOutOfRange)
+                    }
+                }
+                None => Err(errors::UmError::InvalidArrayId),
+            }
+        }
+    }
+
+    fn write_array(
+        &mut self,
+        array_id: instructions::ArrayId,
+        offset: instructions::Offset,
+        val: Word,
+    ) -> Result<(), errors::UmError> {
+        if array_id.0 == 0 {
+            if (offset.0 as usize) < self.program.len() {
+                Arc::make_mut(&mut self.program)[offset.0 as usize] = val;
+                if let Some(inst) = self.decoded.get_mut(offset.0 as usize) {
+                    *inst = None;
+                }
+                #[cfg(feature = "jit")]
+                self.jit.invalidate(offset.0 as usize);
+                Ok(())
+            } else {
+                Err(errors::UmError::ProgramOutOfRange)
+            }
+        } else {
+            match self.data_arrays.get_mut(array_id) {
+                Some(array) => {
+                    if (offset.0 as usize) < array.len() {
+                        Arc::make_mut(array)[offset.0 as usize] = val;
+                        Ok(())
+                    } else {
+                        Err(errors::UmError::ArrayOutOfRange)
+                    }
+                }
+                None => Err(errors::UmError::InvalidArrayId),
+            }
+        }
+    }
+
+    fn allocate_array(&mut self, size: Word) -> Result<instructions::ArrayId, errors::UmError> {
+        self.limits.check_allocation(size, &self.data_arrays)?;
+        let new_array: Arc<[Word]> = std::iter::repeat_n(0, size as usize).collect();
+        let array_id = self.data_arrays.allocate(new_array)?;
+        if let Some(stats) = &mut self.stats {
+            stats.allocated(self.data_arrays.len(), self.data_arrays.platters());
+        }
+        Ok(array_id)
+    }
+
+    fn abandon_array(&mut self, array_id: instructions::ArrayId) -> Result<(), errors::UmError> {
+        if array_id.0 == 0 {
+            Err(errors::UmError::CannotAbandonProgram)
+        } else {
+            match self.data_arrays.abandon(array_id) {
+                Some(_) => Ok(()),
+                None => Err(errors::UmError::InvalidArrayId),
+            }
+        }
+    }
+
+    #[inline(always)]
+    fn execute_instruction(
+        &mut self,
+        inst: instructions::Instruction,
+    ) -> Result<Continue, errors::UmError> {
+        use instructions::Instruction;
+
+        match inst {
+            Instruction::ConditionalMove { dest, src, test } => {
+                let test_val = self.read_register(test)?;
+                if test_val != 0 {
+                    self.set_register(dest, self.read_register(src)?)?;
+                }
+                Ok(Continue::Yes)
+            }
+            Instruction::ArrayIndex {
+                dest,
+                offset,
+                array,
+            } => {
+                let offset_val = self.read_register(offset)?;
+                let array_id = self.read_register(array)?;
+                let val = self.read_array(array_id, offset_val)?;
+                self.set_register(dest, val)?;
+                Ok(Continue::Yes)
+            }
+            Instruction::ArrayAmend { array, offset, val } => {
+                let offset_val = self.read_register(offset)?;
+                let array_id = self.read_register(array)?;
+                let val_val = self.read_register(val)?;
+                self.write_array(array_id, offset_val, val_val)?;
+                Ok(Continue::Yes)
+            }
+            Instruction::Add { dest, x, y } => {
+                let x_val = self.read_register(x)?;
+                let y_val = self.read_register(y)?;
+                let result = x_val.wrapping_add(y_val);
+                self.set_register(dest, result)?;
+                Ok(Continue::Yes)
+            }
+            Instruction::Multiply { dest, x, y } => {
+                let x_val = self.read_register(x)?;
+                let y_val = self.read_register(y)?;
+                let result = x_val.wrapping_mul(y_val);
+                self.set_register(dest, result)?;
+                Ok(Continue::Yes)
+            }
+            Instruction::Divide { dest, x, y } => {
+                let x_val = self.read_register(x)?;
+                let y_val = self.read_register(y)?;
+                match x_val.checked_div(y_val) {
+                    Some(result) => {
+                        self.set_register(dest, result)?;
+                        Ok(Continue::Yes)
+                    }
+                    None => Err(errors::UmError::DivideByZero),
+                }
+            }
+            Instruction::Nand { dest, x, y } => {
+                let x_val = self.read_register(x)?;
+                let y_val = self.read_register(y)?;
+                let result = !(x_val & y_val);
+                self.set_register(dest, result)?;
+                Ok(Continue::Yes)
+            }
+            Instruction::Halt => Ok(Continue::No),
+            Instruction::Allocate { size, result } => {
+                let size_val = self.read_register(size)?;
+                let array_id = self.allocate_array(size_val)?;
+                self.set_register(result, array_id.0)?;
+                Ok(Continue::Yes)
+            }
+            Instruction::Abandon { which } => {
+                let which_val = self.read_register(which)?;
+                self.abandon_array(which_val)?;
+                Ok(Continue::Yes)
+            }
+            Instruction::Output { val } => {
+                let val_val = self.read_register(val)?;
+                if val_val <= 255 {
+                    self.console
+                        .write_byte_at(self.instructions, val_val as u8)
+                        .map_err(|err| errors::UmError::ConsoleFailure { err })?;
+                    Ok(Continue::Yes)
+                } else {
+                    Err(errors::UmError::InvalidOutput { val: val_val })
+                }
+            }
+            Instruction::Input { dest } => {
+                // Whoever is typing should see any prompt first.
+                self.console
+                    .flush()
+                    .map_err(|err| errors::UmError::ConsoleFailure { err })?;
+                let input = self
+                    .console
+                    .read_byte_at(self.instructions)
+                    .map_err(|err| errors::UmError::ConsoleFailure { err })?;
+                match input {
+                    Some(c) => {
+                        self.set_register(dest, c as Word)?;
+                        Ok(Continue::Yes)
+                    }
+                    None => {
+                        self.set_register(dest, u32::MAX)?;
+                        Ok(Continue::Yes)
+                    }
+                }
+            }
+            Instruction::LoadProgram { from, finger } => {
+                let array_id = self.read_register(from)?;
+                let finger_val = self.read_register(finger)?;
+                if array_id.0 == 0 {
+                    if let Some(stats) = &mut self.stats {
+                        stats.loads_in_place += 1;
+                    }
+                    self.finger = finger_val;
+                    Ok(Continue::Yes)
+                } else {
+                    match self.data_arrays.get(array_id) {
+                        Some(array) => {
+                            if let Some(stats) = &mut self.stats {
+                                stats.loads_other += 1;
+                            }
+                            self.program = Arc::clone(array);
+                            self.decoded.clear();
+                            #[cfg(feature = "jit")]
+                            self.jit.reset();
+                            self.finger = finger_val;
+                            Ok(Continue::Yes)
+                        }
+                        None => Err(errors::UmError::InvalidArrayId),
+                    }
+                }
+            }
+            Instruction::LoadRegister { dest, val } => {
+                self.set_register(dest, val)?;
+                Ok(Continue::Yes)
+            }
+        }
+    }
+
+    /// Runs the compiled block at the finger, if there is one.
+    /// Returns false if the next instruction must be interpreted.
+    #[cfg(feature = "jit")]
+    fn execute_native(&mut self) -> bool {
+        let block = match self.jit.block_at(self.finger, &self.program, JIT_HELPERS) {
+            Some(block) => block,
+            None => return false,
+        };
+        let machine: *mut Machine = self;
+        let exit = unsafe {
+            let registers = (*machine).registers.as_mut_ptr();
+            block.call(registers, machine as *mut c_void)
+        };
+        self.finger = exit.finger;
+        !exit.interpret
+    }
+
+    pub fn finger(&self) -> Word {
+        self.finger
+    }
+
+    pub fn registers(&self) -> &[Word; 8] {
+        &self.registers
+    }
+
+    /// Instructions executed so far, counting each step at a `Halt`.
+    /// Only exact if the machine has been driven by `step`, `run_for` and
+    /// `run_until`: `execute` does not count what the JIT runs natively.
+    pub fn instructions(&self) -> u64 {
+        self.instructions
+    }
+
+    /// Array 0.
+    pub fn program(&self) -> &[Word] {
+        &self.program
+    }
+
+    pub fn data_arrays(&self) -> &ArrayStore {
+        &self.data_arrays
+    }
+
+    /// Contents of the given array, where array 0 is the program.
+    pub fn array(&self, array_id: instructions::ArrayId) -> Option<&[Word]> {
+        if array_id.0 == 0 {
+            Some(&self.program)
+        } else {
+            self.data_arrays.get(array_id).map(|array| &array[..])
+        }
+    }
+
+    /// Traces every instruction from now on, which makes `execute` run
+    /// them all in the interpreter.
+    pub fn set_tracer(&mut self, tracer: Tracer) {
+        self.tracer = Some(tracer);
+    }
+
+    /// Stops tracing, handing back the tracer so it can be finished.
+    pub fn take_tracer(&mut self) -> Option<Tracer> {
+        self.tracer.take()
+    }
+
+    /// Profiles every instruction from now on, which makes `execute` run
+    /// them all in the interpreter.
+    pub fn set_profiler(&mut self, mut profiler: Profiler) {
+        profiler.start(&self.program, self.finger);
+        self.profiler = Some(profiler);
+    }
+
+    /// Stops profiling, handing back the profiler so it can report.
+    pub fn take_profiler(&mut self) -> Option<Profiler> {
+        self.profiler.take()
+    }
+
+    /// Counts what the machine does from now on, including what the JIT runs
+    /// natively.
+    pub fn enable_stats(&mut self) {
+        self.stats = Some(Box::new(Stats::new(
+            self.data_arrays.len(),
+            self.data_arrays.platters(),
+        )));
+        #[cfg(feature = "jit")]
+        if let Some(stats) = &mut self.stats {
+            self.jit.set_counters(Some(jit::Counters {
+                instructions: &mut stats.instructions,
+                operators: stats.operators.as_mut_ptr(),
+                loads_in_place: &mut stats.loads_in_place,
+            }));
+        }
+    }
+
+    pub fn stats(&self) -> Option<&Stats> {
+        self.stats.as_deref()
+    }
+
+    /// Caps what the program may use from now on. An instruction budget
+    /// makes `execute` run every instruction in the interpreter.
+    pub fn set_limits(&mut self, limits: Limits) {
+        self.limits = limits;
+    }
+
+    /// Lets `token` stop `execute`, `run_for` and `run_until`.
+    pub fn set_cancel_token(&mut self, token: CancelToken) {
+        #[cfg(feature = "jit")]
+        self.jit.set_cancel(Some(Arc::clone(&token.cancelled)));
+        self.cancel = Some(token);
+    }
+
+    fn cancelled(&self) -> bool {
+        self.cancel.as_ref().is_some_and(CancelToken::is_cancelled)
+    }
+
+    /// Whether to stop rather than execute `inst`, an `Input` being where a
+    /// cancelled machine could otherwise block indefinitely.
+    #[inline]
+    fn cancelled_before(&self, inst: instructions::Instruction) -> bool {
+        matches!(inst, instructions::Instruction::Input { .. }) && self.cancelled()
+    }
+
+    /// Whether every instruction must go through the interpreter, to be
+    /// watched or counted, rather than some running unseen as native code.
+    #[cfg(feature = "jit")]
+    fn interpret_only(&self) -> bool {
+        self.tracer.is_some() || self.profiler.is_some() || self.limits.instructions.is_some()
+    }
+
+    /// Executes the instruction under the finger, always in the interpreter.
+    ///
+    /// The finger stays on a `Halt`, so stepping a halted machine halts
+    /// again rather than running on past it, and on an `Input` that a
+    /// cancelled machine stopped at.
+    pub fn step(&mut self) -> StepResult {
+        let finger = self.finger;
+        let registers = self.registers;
+        if let Err(err) = self.limits.check_instructions(self.instructions) {
+            return StepResult::Fault(self.fault(err, finger, registers));
+        }
+        let inst = match self.fetch_instruction() {
+            Some(Ok(inst)) => inst,
+            Some(Err(err)) => return StepResult::Fault(self.fault(err, finger, registers)),
+            None => return StepResult::RanOffEnd,
+        };
+        if self.cancelled_before(inst) {
+            self.finger = finger;
+            return StepResult::Cancelled;
+        }
+        self.instructions += 1;
+        if let Some(stats) = &mut self.stats {
+            stats.executed(&inst);
+        }
+        let traced = self.tracer.as_ref().and_then(|tracer| {
+            let platter = self.program[finger as usize];
+            tracer
+                .wants(self.instructions, finger, platter)
+                .then_some(platter)
+        });
+        let result = self.execute_instruction(inst);
+        if let (Some(platter), Some(tracer), Ok(_)) = (traced, &mut self.tracer, &result) {
+            tracer.record(
+                self.instructions,
+                finger,
+                platter,
+                inst,
+                &registers,
+                &self.registers,
+            );
+        }
+        if let (Some(profiler), Ok(_)) = (&mut self.profiler, &result) {
+            profiler.record(
+                self.instructions,
+                finger,
+                inst,
+                &registers,
+                self.finger,
+                &self.program,
+            );
+        }
+        match result {
+            Ok(Continue::Yes) => StepResult::Running,
+            Ok(Continue::No) => {
+                self.finger -= 1;
+                StepResult::Halted
+            }
+            Err(err) => StepResult::Fault(self.fault(err, finger, registers)),
+        }
+    }
+
+    /// `step` for a machine that nothing is watching or counting, which can
+    /// skip keeping the registers from before the instruction: one that
+    /// fails leaves them as it found them.
+    #[inline]
+    fn step_quietly(&mut self) -> StepResult {
+        let finger = self.finger;
+        let inst = match self.fetch_instruction() {
+            Some(Ok(inst)) => inst,
+            Some(Err(err)) => return StepResult::Fault(self.fault(err, finger, self.registers)),
+            None => return StepResult::RanOffEnd,
+        };
+        if self.cancelled_before(inst) {
+            self.finger = finger;
+            return StepResult::Cancelled;
+        }
+        self.instructions += 1;
+        match self.execute_instruction(inst) {
+            Ok(Continue::Yes) => StepResult::Running,
+            Ok(Continue::No) => {
+                self.finger -= 1;
+                StepResult::Halted
+            }
+            Err(err) => StepResult::Fault(self.fault(err, finger, self.registers)),
+        }
+    }
+
+    /// Describes `error`, raised by the instruction at `finger` when the
+    /// registers held `registers`.
+    fn fault(&self, error: errors::UmError, finger: Word, registers: [Word; 8]) -> errors::Fault {
+        let word = self.program.get(finger as usize).copied();
+        let instruction = word.and_then(|word| instructions::Instruction::decode_from(word).ok());
+        let reg = |idx: u8| registers.get(idx as usize).copied();
+        let addressed = match instruction {
+            Some(instructions::Instruction::ArrayIndex { offset, array, .. })
+            | Some(instructions::Instruction::ArrayAmend { array, offset, .. }) => {
+                reg(array.idx).map(|id| (id, reg(offset.idx)))
+            }
+            Some(instructions::Instruction::Abandon { which }) => {
+                reg(which.idx).map(|id| (id, None))
+            }
+            Some(instructions::Instruction::LoadProgram { from, finger }) => {
+                reg(from.idx).map(|id| (id, reg(finger.idx)))
+            }
+            _ => None,
+        };
+        let array = addressed.map(|(id, offset)| errors::ArrayContext {
+            id: instructions::ArrayId(id),
+            offset,
+            len: self
+                .array(instructions::ArrayId(id))
+                .map(|array| array.len()),
+        });
+        errors::Fault {
+            error,
+            finger,
+            word,
+            instruction,
+            registers,
+            array,
+        }
+    }
+
+    /// Executes at most `n` instructions, stopping early if the machine does
+    /// or is cancelled.
+    pub fn run_for(&mut self, n: u64) -> StepResult {
+        for i in 0..n {
+            if i.is_multiple_of(CANCEL_INTERVAL) && self.cancelled() {
+                return StepResult::Cancelled;
+            }
+            match self.step() {
+                StepResult::Running => {}
+                stopped => return stopped,
+            }
+        }
+        StepResult::Running
+    }
+
+    /// Executes instructions until `predicate` holds for the machine, which
+    /// is checked before each one, or until the machine stops or is
+    /// cancelled.
+    pub fn run_until<P: FnMut(&Machine) -> bool>(&mut self, mut predicate: P) -> StepResult {
+        let mut i: u64 = 0;
+        while !predicate(self) {
+            if i.is_multiple_of(CANCEL_INTERVAL) && self.cancelled() {
+                return StepResult::Cancelled;
+            }
+            i += 1;
+            match self.step() {
+                StepResult::Running => {}
+                stopped => return stopped,
+            }
+        }
+        StepResult::Running
+    }
+
+    /// Saves the complete state of the machine, which `restore` can later
+    /// resume from. The format is a sequence of big-endian words:
+    ///
+    /// | field         | words | contents                                    |
+    /// |---------------|-------|---------------------------------------------|
+    /// | magic         | 1     | `0x554d5353`, "UMSS" in ASCII               |
+    /// | version       | 1     | `2`                                         |
+    /// | finger        | 1     |                                             |
+    /// | registers     | 8     | `r0` to `r7`                                |
+    /// | instructions  | 2     | the instruction count, high word first      |
+    /// | program       |       | length, then the platters of array 0        |
+    /// | slot count    | 1     | highest array identifier ever issued        |
+    /// | slots         |       | for identifiers 1 up: 0 if abandoned, else  |
+    /// |               |       | 1, the length and the platters              |
+    /// | free list     |       | count, then identifiers in reuse order      |
+    /// | pending input |       | length in bytes, then the bytes padded with |
+    /// |               |       | zeroes to a whole number of words           |
+    ///
+    /// The free list is kept so that a resumed machine hands out the same
+    /// identifiers as the original would have.
+    pub fn snapshot<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        write_word(out, SNAPSHOT_MAGIC)?;
+        write_word(out, SNAPSHOT_VERSION)?;
+        write_word(out, self.finger)?;
+        for &reg in &self.registers {
+            write_word(out, reg)?;
+        }
+        write_word(out, (self.instructions >> 32) as Word)?;
+        write_word(out, self.instructions as Word)?;
+        write_platters(out, &self.program)?;
+        let slots = self.data_arrays.slots();
+        write_word(out, slots.len() as Word)?;
+        for slot in slots {
+            match slot {
+                Some(array) => {
+                    write_word(out, 1)?;
+                    write_platters(out, array)?;
+                }
+                None => write_word(out, 0)?,
+            }
+        }
+        write_platters(out, self.data_arrays.free())?;
+        write_bytes(out, &self.console.pending_input())?;
+        out.flush()
+    }
+
+    /// Resumes a machine saved by `snapshot`, attached to `console`. Input
+    /// that was pending when the snapshot was taken is read first.
+    pub fn restore<R: Read>(input: &mut R, console: Box<dyn Console>) -> io::Result<Machine> {
+        read_header(input, SNAPSHOT_MAGIC, SNAPSHOT_VERSION, "snapshot")?;
+        let finger = read_word(input)?;
+        let mut registers = [0; 8];
+        for reg in &mut registers {
+            *reg = read_word(input)?;
+        }
+        let high = read_word(input)?;
+        let instructions = (u64::from(high) << 32) | u64::from(read_word(input)?);
+        let program = read_platters(input)?;
+        let num_slots = read_word(input)?;
+        let mut slots = Vec::new();
+        for _ in 0..num_slots {
+            match read_word(input)? {
+                0 => slots.push(None),
+                1 => slots.push(Some(Arc::from(read_platters(input)?))),
+                _ => return Err(invalid_data("corrupt array slot")),
+            }
+        }
+        let free = read_platters(input)?;
+        let mut unique_free = free.clone();
+        unique_free.sort_unstable();
+        unique_free.dedup();
+        let num_abandoned = slots.iter().filter(|slot| slot.is_none()).count();
+        let abandoned = |id: &Word| matches!(slots.get((*id as usize).wrapping_sub(1)), Some(None));
+        if unique_free.len() != free.len()
+            || free.len() != num_abandoned
+            || !free.iter().all(abandoned)
+        {
+            return Err(invalid_data(
+                "free list does not match the abandoned arrays",
+            ));
+        }
+        let pending = read_bytes(input)?;
+        let console: Box<dyn Console> = if pending.is_empty() {
+            console
+        } else {
+            Box::new(ResumedConsole::new(pending, console))
+        };
+        Ok(Machine {
+            finger,
+            registers,
+            program: Arc::from(program),
+            decoded: Vec::new(),
+            data_arrays: ArrayStore::from_parts(slots, free),
+            console,
+            instructions,
+            tracer: None,
+            profiler: None,
+            stats: None,
+            limits: Limits::default(),
+            cancel: None,
+            #[cfg(feature = "jit")]
+            jit: jit::Jit::new(),
+        })
+    }
+
+    /// Pushes out the program's buffered output. A failure is a fault at
+    /// the current finger.
+    pub fn flush_console(&mut self) -> Result<(), errors::Fault> {
+        self.console.flush().map_err(|err| {
+            let error = errors::UmError::ConsoleFailure { err };
+            self.fault(error, self.finger, self.registers)
+        })
+    }
+
+    /// Starts the universal machine.
+    /// Runs indefinitely until an error, the end of a program or
+    /// cancellation.
+    pub fn execute(&mut self) -> ExitReason {
+        let reason = self.run();
+        self.finish(reason)
+    }
+
+    /// Flushes the console once the machine has stopped for `reason`. A
+    /// failure to flush turns any other reason into a fault.
+    pub fn finish(&mut self, reason: ExitReason) -> ExitReason {
+        match (self.flush_console(), reason) {
+            (Err(fault), ExitReason::Halted | ExitReason::RanOffEnd | ExitReason::Cancelled) => {
+                ExitReason::Faulted(fault)
+            }
+            (_, reason) => reason,
+        }
+    }
+
+    fn run(&mut self) -> ExitReason {
+        // Nothing can start watching the machine while it runs.
+        let quiet = self.tracer.is_none()
+            && self.profiler.is_none()
+            && self.stats.is_none()
+            && self.limits.instructions.is_none();
+        let mut until_check = 0;
+        loop {
+            if until_check == 0 {
+                if self.cancelled() {
+                    return ExitReason::Cancelled;
+                }
+                until_check = CANCEL_INTERVAL;
+            }
+            #[cfg(feature = "jit")]
+            {
+                if !self.interpret_only() && self.execute_native() {
+                    // Native code leaves at a jump when cancelled, so check
+                    // again before going on.
+                    until_check = 0;
+                    continue;
+                }
+            }
+            until_check -= 1;
+            let result = if quiet {
+                self.step_quietly()
+            } else {
+                self.step()
+            };
+            match result {
+                StepResult::Running => {}
+                StepResult::Halted => return ExitReason::Halted,
+                StepResult::RanOffEnd => return ExitReason::RanOffEnd,
+                StepResult::Fault(err) => return ExitReason::Faulted(err),
+                StepResult::Cancelled => return ExitReason::Cancelled,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "jit")]
+const JIT_HELPERS: jit::Helpers = jit::Helpers {
+    array_index: jit_array_index,
+    array_amend: jit_array_amend,
+    allocate: jit_allocate,
+    abandon: jit_abandon,
+};
+
+/// Executes an `ArrayIndex` on behalf of a compiled block.
+#[cfg(feature = "jit")]
+extern "sysv64" fn jit_array_index(machine: *mut c_void, word: Word) -> u32 {
+    let machine = unsafe { &mut *(machine as *mut Machine) };
+    let regs = &machine.registers;
+    let array = instructions::ArrayId(regs[(word >> 3 & 7) as usize]);
+    let offset = instructions::Offset(regs[(word & 7) as usize]);
+    match machine.read_array(array, offset) {
+        Ok(val) => {
+            machine.registers[(word >> 6 & 7) as usize] = val;
+            jit::HELPER_CONTINUE
+        }
+        Err(_) => jit::HELPER_FAULT,
+    }
+}
+
+/// Executes an `ArrayAmend` on behalf of a compiled block.
+#[cfg(feature = "jit")]
+extern "sysv64" fn jit_array_amend(machine: *mut c_void, word: Word) -> u32 {
+    let machine = unsafe { &mut *(machine as *mut Machine) };
+    let regs = &machine.registers;
+    let array = instructions::ArrayId(regs[(word >> 6 & 7) as usize]);
+    let offset = instructions::Offset(regs[(word >> 3 & 7) as usize]);
+    let val = regs[(word & 7) as usize];
+    match machine.write_array(array, offset, val) {
+        Ok(()) if machine.jit.take_dirty() => jit::HELPER_EXIT,
+        Ok(()) => jit::HELPER_CONTINUE,
+        Err(_) => jit::HELPER_FAULT,
+    }
+}
+
+/// Executes an `Allocate` on behalf of a compiled block.
+#[cfg(feature = "jit")]
+extern "sysv64" fn jit_allocate(machine: *mut c_void, word: Word) -> u32 {
+    let machine = unsafe { &mut *(machine as *mut Machine) };
+    match machine.allocate_array(machine.registers[(word & 7) as usize]) {
+        Ok(array_id) => {
+            machine.registers[(word >> 3 & 7) as usize] = array_id.0;
+            jit::HELPER_CONTINUE
+        }
+        Err(_) => jit::HELPER_FAULT,
+    }
+}
+
+/// Executes an `Abandon` on behalf of a compiled block.
+#[cfg(feature = "jit")]
+extern "sysv64" fn jit_abandon(machine: *mut c_void, word: Word) -> u32 {
+    let machine = unsafe { &mut *(machine as *mut Machine) };
+    let array_id = instructions::ArrayId(machine.registers[(word & 7) as usize]);
+    match machine.abandon_array(array_id) {
+        Ok(()) => jit::HELPER_CONTINUE,
+        Err(_) => jit::HELPER_FAULT,
+    }
+}
@@ -1,13 +1,559 @@
-mod um;
+//! Runs a universal machine program.
+//!
+//! The exit status tells why the machine stopped:
+//!
+//! - 0: the program executed `Halt`.
+//! - 1: the machine faulted, or something else went wrong, such as a trace
+//!   that could not be written or a script or replay left unfinished.
+//! - 2: the arguments were not understood.
+//! - 3: the program ran off the end of array 0 without halting.
+//! - 4: `--timeout` expired.
+//!
+//! Under `--debug` the status is the same once the machine has stopped, and
+//! 0 if the debugger is quit before then.
 
+mod debugger;
+mod signals;
+
+use an_urgent_appeal::console::{Console, StdioConsole};
+use an_urgent_appeal::dump::CrashDump;
+use an_urgent_appeal::errors::Fault;
+use an_urgent_appeal::instructions::MNEMONICS;
+use an_urgent_appeal::limits::Limits;
+use an_urgent_appeal::listing::parse_number;
+use an_urgent_appeal::machine::{CancelToken, ExitReason, Machine, StepResult, Word};
+use an_urgent_appeal::profile::Profiler;
+use an_urgent_appeal::replay::{RecordingConsole, ReplayConsole};
+use an_urgent_appeal::script::{Script, ScriptConsole};
+use an_urgent_appeal::trace::{Filter, Format, Tracer};
 use std::env;
-use std::fs;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::ops::Range;
+use std::process;
+use std::thread;
+use std::time::{Duration, Instant};
+
+const EXIT_FAILED: i32 = 1;
+const EXIT_USAGE: i32 = 2;
+const EXIT_RAN_OFF_END: i32 = 3;
+const EXIT_TIMED_OUT: i32 = 4;
+
+const USAGE: &str = "usage: an_urgent_appeal [--debug] [--crash-dump <file>] [--snapshot <file>] \
+                     [--record <file> | --replay <file>] [--script <file>] \
+                     [--trace <file> [--trace-format text|binary] [--trace-fingers <range>] \
+                     [--trace-ops <op>,...] [--trace-window <range>]] \
+                     [--profile <report> [--profile-period <n>]] [--stats] \
+                     [--max-instructions <n>] [--max-platters <n>] [--max-arrays <n>] \
+                     [--max-allocation <n>] [--timeout <seconds>] \
+                     (<program> | --restore <snapshot>)";
+
+/// Instructions run between checks for a script timeout.
+const SLICE: u64 = 1 << 20;
+
+struct Options {
+    program: Option<String>,
+    debug: bool,
+    /// Where to write a crash dump if the machine faults.
+    crash_dump: Option<String>,
+    /// Where to save a snapshot whenever SIGUSR1 arrives, and if the machine
+    /// times out.
+    snapshot: Option<String>,
+    /// A snapshot to resume instead of starting a program.
+    restore: Option<String>,
+    /// Where to log the input the program reads.
+    record: Option<String>,
+    /// A log of input to read instead of stdin.
+    replay: Option<String>,
+    /// An expect script to drive the program with.
+    script: Option<String>,
+    /// Where to write a trace of the instructions executed.
+    trace: Option<String>,
+    trace_format: Format,
+    trace_filter: Filter,
+    /// Where to write a profile report, with folded stacks beside it.
+    profile: Option<String>,
+    /// Profile every this many instructions.
+    profile_period: u64,
+    /// Print statistics to stderr when the machine stops.
+    stats: bool,
+    limits: Limits,
+    /// Wall-clock time after which to stop the machine. A machine blocked
+    /// reading input stops once the input arrives, not before.
+    timeout: Option<Duration>,
+}
+
+fn parse_args() -> Options {
+    let mut options = Options {
+        program: None,
+        debug: false,
+        crash_dump: None,
+        snapshot: None,
+        restore: None,
+        record: None,
+        replay: None,
+        script: None,
+        trace: None,
+        trace_format: Format::Text,
+        trace_filter: Filter::default(),
+        profile: None,
+        profile_period: 1,
+        stats: false,
+        limits: Limits::default(),
+        timeout: None,
+    };
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--debug" => options.debug = true,
+            "--stats" => options.stats = true,
+            "--max-instructions" => options.limits.instructions = Some(parse_limit(args.next())),
+            "--max-platters" => options.limits.platters = Some(parse_limit(args.next())),
+            "--max-arrays" => options.limits.arrays = Some(parse_limit(args.next()) as usize),
+            "--timeout" => {
+                let seconds = args.next().and_then(|arg| arg.parse().ok());
+                let seconds = seconds.unwrap_or_else(|| usage());
+                let timeout = Duration::try_from_secs_f64(seconds).unwrap_or_else(|_| usage());
+                options.timeout = Some(timeout);
+            }
+            "--max-allocation" => {
+                let limit = parse_limit(args.next()).min(Word::MAX.into());
+                options.limits.allocation = Some(limit as Word);
+            }
+            "--crash-dump" => options.crash_dump = Some(args.next().unwrap_or_else(|| usage())),
+            "--snapshot" => options.snapshot = Some(args.next().unwrap_or_else(|| usage())),
+            "--restore" => options.restore = Some(args.next().unwrap_or_else(|| usage())),
+            "--record" => options.record = Some(args.next().unwrap_or_else(|| usage())),
+            "--replay" => options.replay = Some(args.next().unwrap_or_else(|| usage())),
+            "--script" => options.script = Some(args.next().unwrap_or_else(|| usage())),
+            "--trace" => options.trace = Some(args.next().unwrap_or_else(|| usage())),
+            "--trace-format" => {
+                options.trace_format = match args.next().as_deref() {
+                    Some("text") => Format::Text,
+                    Some("binary") => Format::Binary,
+                    _ => usage(),
+                }
+            }
+            "--trace-fingers" => {
+                let range = parse_range(args.next());
+                let to_word = |n: u64| n.min(Word::MAX.into()) as Word;
+                let range = to_word(range.start)..to_word(range.end);
+                options.trace_filter.fingers.push(range);
+            }
+            "--trace-ops" => {
+                let ops = args.next().unwrap_or_else(|| usage());
+                for op in ops.split(',') {
+                    let number = match MNEMONICS.iter().position(|&name| name == op) {
+                        Some(number) => number as Word,
+                        None => parse_number(op)
+                            .filter(|&n: &Word| n < 14)
+                            .unwrap_or_else(|| usage()),
+                    };
+                    options.trace_filter.operators.push(number);
+                }
+            }
+            "--trace-window" => options.trace_filter.window = Some(parse_range(args.next())),
+            "--profile" => options.profile = Some(args.next().unwrap_or_else(|| usage())),
+            "--profile-period" => {
+                options.profile_period = args
+                    .next()
+                    .as_deref()
+                    .and_then(parse_number)
+                    .filter(|&n| n > 0)
+                    .unwrap_or_else(|| usage())
+            }
+            _ if arg.starts_with("--") || options.program.is_some() => usage(),
+            _ => options.program = Some(arg),
+        }
+    }
+    let filter = &options.trace_filter;
+    let traced = !filter.fingers.is_empty()
+        || !filter.operators.is_empty()
+        || filter.window.is_some()
+        || options.trace_format != Format::Text;
+    if options.program.is_some() == options.restore.is_some()
+        || options.replay.is_some() && (options.record.is_some() || options.script.is_some())
+        || traced && options.trace.is_none()
+        || options.profile_period != 1 && options.profile.is_none()
+    {
+        usage();
+    }
+    options
+}
+
+fn parse_limit(arg: Option<String>) -> u64 {
+    arg.as_deref()
+        .and_then(parse_number)
+        .unwrap_or_else(|| usage())
+}
+
+/// Parses `<from>..<to>`, the half-open range between them, `<from>..`,
+/// everything from `<from>` on, or a single number.
+fn parse_range(arg: Option<String>) -> Range<u64> {
+    let arg = arg.unwrap_or_else(|| usage());
+    let number = |arg| parse_number(arg).unwrap_or_else(|| usage());
+    match arg.split_once("..") {
+        Some((from, "")) => number(from)..u64::MAX,
+        Some((from, to)) => number(from)..number(to),
+        None => number(&arg)..number(&arg).saturating_add(1),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(EXIT_USAGE);
+}
+
+/// Consoles inside the machine that are checked on while it runs.
+#[derive(Default)]
+struct Watched {
+    replay: Option<ReplayConsole>,
+    script: Option<ScriptConsole>,
+}
+
+/// Fails if the script is still waiting for output, or if the replay left
+/// recorded input unread.
+fn check_finished(watched: &Watched) {
+    if let Some(text) = watched.script.as_ref().and_then(ScriptConsole::expecting) {
+        fail(&format!(
+            "script: program stopped while waiting for \"{}\"",
+            String::from_utf8_lossy(&text).escape_debug()
+        ));
+    }
+    if let Some(unread) = watched
+        .replay
+        .as_ref()
+        .map(|replay| replay.remaining().len())
+        .filter(|&n| n > 0)
+    {
+        fail(&format!(
+            "replay diverged: {} recorded inputs were never read",
+            unread
+        ));
+    }
+}
+
+/// Stdio, or a replayed log, optionally driven by a script and recorded.
+fn console(options: &Options) -> (Box<dyn Console>, Watched) {
+    let mut watched = Watched::default();
+    let mut console: Box<dyn Console> = Box::new(StdioConsole::new());
+    if let Some(path) = &options.replay {
+        let replay = File::open(path)
+            .and_then(|file| ReplayConsole::new(&mut BufReader::new(file), console))
+            .unwrap_or_else(|err| fail(&format!("unable to replay {}: {}", path, err)));
+        watched.replay = Some(replay.clone());
+        console = Box::new(replay);
+    }
+    if let Some(path) = &options.script {
+        let source = fs::read_to_string(path)
+            .unwrap_or_else(|err| fail(&format!("unable to read script {}: {}", path, err)));
+        let script =
+            Script::parse(&source).unwrap_or_else(|err| fail(&format!("{}: {}", path, err)));
+        let script = ScriptConsole::new(script, console);
+        watched.script = Some(script.clone());
+        console = Box::new(script);
+    }
+    if let Some(path) = &options.record {
+        let recording = File::create(path)
+            .and_then(|file| RecordingConsole::new(console, file))
+            .unwrap_or_else(|err| fail(&format!("unable to record to {}: {}", path, err)));
+        console = Box::new(recording);
+    }
+    (console, watched)
+}
+
+fn load(options: &Options, console: Box<dyn Console>) -> Machine {
+    if let Some(path) = &options.restore {
+        File::open(path)
+            .and_then(|file| Machine::restore(&mut BufReader::new(file), console))
+            .unwrap_or_else(|err| fail(&format!("unable to restore {}: {}", path, err)))
+    } else {
+        let filename = options.program.as_ref().unwrap();
+        let program = fs::read(filename)
+            .unwrap_or_else(|err| fail(&format!("unable to read {}: {}", filename, err)));
+        Machine::with_console(program, console)
+    }
+}
+
+/// Starts tracing to the file given by `--trace`, if any.
+fn start_trace(options: &Options, m: &mut Machine) {
+    if let Some(path) = &options.trace {
+        let tracer = File::create(path)
+            .and_then(|file| {
+                Tracer::new(
+                    Box::new(file),
+                    options.trace_format,
+                    options.trace_filter.clone(),
+                )
+            })
+            .unwrap_or_else(|err| fail(&format!("unable to trace to {}: {}", path, err)));
+        m.set_tracer(tracer);
+    }
+}
+
+/// Finishes the trace, if there is one, returning false if it could not be
+/// written in full.
+fn finish_trace(m: &mut Machine) -> bool {
+    match m.take_tracer().map(Tracer::finish) {
+        Some(Err(err)) => {
+            eprintln!("unable to write trace: {}", err);
+            false
+        }
+        _ => true,
+    }
+}
+
+/// Writes the profile, if there is one, to the report file and its folded
+/// stacks to the same path with `.folded` added. Returns false if either
+/// could not be written.
+fn finish_profile(options: &Options, m: &mut Machine) -> bool {
+    let (Some(profiler), Some(path)) = (m.take_profiler(), &options.profile) else {
+        return true;
+    };
+    let folded = format!("{}.folded", path);
+    let written = File::create(path)
+        .and_then(|file| profiler.write_report(&mut BufWriter::new(file)))
+        .map_err(|err| (path, err))
+        .and_then(|()| {
+            File::create(&folded)
+                .and_then(|file| profiler.write_folded(&mut BufWriter::new(file)))
+                .map_err(|err| (&folded, err))
+        });
+    match written {
+        Ok(()) => true,
+        Err((path, err)) => {
+            eprintln!("unable to write profile to {}: {}", path, err);
+            false
+        }
+    }
+}
+
+/// Prints the statistics, if they were kept, for a run that took `elapsed`.
+fn print_stats(m: &Machine, elapsed: Duration) {
+    if let Some(stats) = m.stats() {
+        let _ = stats.write_summary(&mut io::stderr().lock(), elapsed);
+    }
+}
+
+fn fail(message: &str) -> ! {
+    eprintln!("{}", message);
+    process::exit(EXIT_FAILED);
+}
+
+fn write_crash_dump(m: &Machine, fault: &Fault, path: &str) {
+    let dump = CrashDump::capture(m, fault);
+    let written = File::create(path).and_then(|file| dump.write_to(&mut BufWriter::new(file)));
+    match written {
+        Ok(()) => eprintln!("crash dump written to {}", path),
+        Err(err) => eprintln!("unable to write crash dump to {}: {}", path, err),
+    }
+}
+
+/// The exit status for a machine that stopped for `reason`.
+fn exit_status(reason: &ExitReason) -> i32 {
+    match reason {
+        ExitReason::Halted => 0,
+        ExitReason::RanOffEnd => EXIT_RAN_OFF_END,
+        ExitReason::Faulted(_) => EXIT_FAILED,
+        ExitReason::Cancelled => EXIT_TIMED_OUT,
+    }
+}
+
+/// Writes the crash dump or the snapshot asked for when the machine stops
+/// for `reason`.
+fn save_state(options: &Options, m: &mut Machine, reason: &ExitReason) {
+    match reason {
+        ExitReason::Faulted(fault) => {
+            if let Some(path) = &options.crash_dump {
+                write_crash_dump(m, fault, path);
+            }
+        }
+        ExitReason::Cancelled => {
+            if let Some(path) = &options.snapshot {
+                save_snapshot(m, path);
+            }
+        }
+        ExitReason::Halted | ExitReason::RanOffEnd => {}
+    }
+}
+
+/// Saves a snapshot to `path`, first flushing the console so that output
+/// from before the snapshot is not lost with the process.
+fn save_snapshot(m: &mut Machine, path: &str) {
+    if let Err(err) = m.flush_console() {
+        eprintln!("unable to flush output: {}", err);
+    }
+    let saved = File::create(path).and_then(|file| m.snapshot(&mut BufWriter::new(file)));
+    match saved {
+        Ok(()) => eprintln!("snapshot written to {}", path),
+        Err(err) => eprintln!("unable to write snapshot to {}: {}", path, err),
+    }
+}
+
+/// Cancels `token` once `timeout` has passed.
+fn start_timer(token: CancelToken, timeout: Duration) {
+    thread::spawn(move || {
+        thread::sleep(timeout);
+        token.cancel();
+    });
+}
+
+/// What cancels the machine: the timer, which fires no sooner than
+/// `deadline`, and SIGUSR1 if snapshots are saved to `snapshot`.
+struct Cancellation<'a> {
+    token: CancelToken,
+    deadline: Option<Instant>,
+    snapshot: Option<&'a str>,
+}
+
+impl Cancellation<'_> {
+    /// Whether a machine that was cancelled should run on, which it should
+    /// if SIGUSR1 rather than the timer stopped it, once the snapshot is
+    /// saved. A machine stopped before the deadline can only have been
+    /// stopped by the signal.
+    fn resume(&self, m: &mut Machine) -> bool {
+        let Some(path) = self.snapshot else {
+            return false;
+        };
+        // Reset before looking at the clock, so that a timer firing after
+        // the check cancels the machine again.
+        self.token.reset();
+        if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            return false;
+        }
+        save_snapshot(m, path);
+        true
+    }
+}
+
+/// Runs the machine like `execute`, saving a snapshot whenever SIGUSR1
+/// arrives.
+fn execute_with_snapshots(m: &mut Machine, cancellation: &Cancellation) -> ExitReason {
+    loop {
+        match m.execute() {
+            ExitReason::Cancelled if cancellation.resume(m) => {}
+            reason => return reason,
+        }
+    }
+}
+
+/// Runs the machine like `execute_with_snapshots`, but in the interpreter
+/// alone so that every instruction is counted, and in slices that never run
+/// past the deadline of the script's current `expect`.
+fn execute_in_slices(
+    m: &mut Machine,
+    cancellation: &Cancellation,
+    script: Option<&ScriptConsole>,
+) -> ExitReason {
+    let deadline = || script.and_then(ScriptConsole::deadline);
+    loop {
+        let slice = deadline().map_or(SLICE, |deadline| {
+            deadline.saturating_sub(m.instructions()).clamp(1, SLICE)
+        });
+        let stopped = m.run_for(slice);
+        match stopped {
+            StepResult::Running => {
+                if deadline().is_some_and(|deadline| m.instructions() >= deadline) {
+                    let _ = m.flush_console();
+                    finish_trace(m);
+                    let text = script
+                        .and_then(ScriptConsole::expecting)
+                        .unwrap_or_default();
+                    fail(&format!(
+                        "script timed out waiting for \"{}\"",
+                        String::from_utf8_lossy(&text).escape_debug()
+                    ));
+                }
+            }
+            StepResult::Halted => return m.finish(ExitReason::Halted),
+            StepResult::RanOffEnd => return m.finish(ExitReason::RanOffEnd),
+            StepResult::Cancelled if cancellation.resume(m) => {}
+            StepResult::Cancelled => return m.finish(ExitReason::Cancelled),
+            StepResult::Fault(fault) => return m.finish(ExitReason::Faulted(fault)),
+        }
+    }
+}
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
-    assert!(args.len() == 2);
-    let filename = &args[1];
-    let program = fs::read(filename).expect("Unable to load program");
-    let m = um::machine::Machine::new(program);
-    m.execute().unwrap();
+    let options = parse_args();
+    let (console, watched) = console(&options);
+    let mut m = load(&options, console);
+    m.set_limits(options.limits);
+    start_trace(&options, &mut m);
+    if options.profile.is_some() {
+        m.set_profiler(Profiler::new(options.profile_period));
+    }
+    if options.stats {
+        m.enable_stats();
+    }
+    let started = Instant::now();
+    let cancellation = Cancellation {
+        token: CancelToken::new(),
+        deadline: options
+            .timeout
+            .and_then(|timeout| started.checked_add(timeout)),
+        // The debugger saves a snapshot only when the machine times out.
+        snapshot: options.snapshot.as_deref().filter(|_| !options.debug),
+    };
+    if options.timeout.is_some() || cancellation.snapshot.is_some() {
+        m.set_cancel_token(cancellation.token.clone());
+    }
+    if let Some(timeout) = options.timeout {
+        start_timer(cancellation.token.clone(), timeout);
+    }
+    if cancellation.snapshot.is_some() {
+        signals::install(cancellation.token.clone());
+    }
+    if options.debug {
+        let (mut m, reason) = debugger::Debugger::new(m)
+            .run()
+            .unwrap_or_else(|err| fail(&format!("unable to read debugger commands: {}", err)));
+        print_stats(&m, started.elapsed());
+        let traced = finish_trace(&mut m);
+        let profiled = finish_profile(&options, &mut m);
+        if let Some(reason) = &reason {
+            save_state(&options, &mut m, reason);
+        }
+        match reason.as_ref().map_or(0, exit_status) {
+            0 if !(traced && profiled) => process::exit(EXIT_FAILED),
+            0 => {}
+            status => process::exit(status),
+        }
+    } else {
+        let counted =
+            options.record.is_some() || options.replay.is_some() || options.script.is_some();
+        let reason = if counted {
+            execute_in_slices(&mut m, &cancellation, watched.script.as_ref())
+        } else {
+            execute_with_snapshots(&mut m, &cancellation)
+        };
+        let elapsed = started.elapsed();
+        let traced = finish_trace(&mut m);
+        let profiled = finish_profile(&options, &mut m);
+        match &reason {
+            ExitReason::Halted => {}
+            ExitReason::RanOffEnd => eprintln!(
+                "machine failed: ran off the end of the program at finger {:#010x}",
+                m.finger()
+            ),
+            ExitReason::Faulted(fault) => eprintln!("machine failed: {}", fault),
+            ExitReason::Cancelled => {
+                let timeout = options.timeout.unwrap_or_default();
+                eprintln!("machine timed out after {}s", timeout.as_secs_f64());
+            }
+        }
+        print_stats(&m, elapsed);
+        save_state(&options, &mut m, &reason);
+        match exit_status(&reason) {
+            0 => {}
+            status => process::exit(status),
+        }
+        if !(traced && profiled) {
+            process::exit(EXIT_FAILED);
+        }
+    }
+    check_finished(&watched);
 }
@@ -0,0 +1,305 @@
+//! Counting where a program spends its instructions.
+//!
+//! The profiler counts executions of each finger, separately for each
+//! distinct program that `LoadProgram` installs as array 0, along with
+//! totals per operator and the arrays allocated and abandoned. It writes
+//! two files: a report of the hottest instructions with their disassembly,
+//! and folded stacks for flamegraph tools.
+//!
+//! A UM program has no call instruction, so stacks are guessed from the
+//! jumps `LoadProgram` makes within array 0. Every jump is taken as a call
+//! to its target, except that a jump to just after the site of a call on
+//! the stack returns from it, and a jump to the target of a call on the
+//! stack, as a loop or recursion makes, goes back to that call. Loading a
+//! different program starts a new stack.
+//!
+//! With a sampling period above 1, only every so many instructions are
+//! counted, which makes the counts approximate and the run faster.
+//! Allocations and jumps are always followed exactly.
+
+use crate::instructions::{Instruction, MNEMONICS};
+use crate::machine::Word;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+use std::io::{self, Write};
+
+/// Instructions listed in the report.
+const HOT_SPOTS: usize = 50;
+
+/// Deepest stack kept. Deeper calls replace the innermost frame, so that
+/// jumps the heuristic mistakes for calls cannot grow the stack forever.
+const MAX_DEPTH: usize = 64;
+
+pub struct Profiler {
+    /// Count only every `period`th instruction.
+    period: u64,
+    /// Each distinct program seen, in the order first loaded. These are
+    /// copies, so that array 0 stays unshared and can be amended in place.
+    programs: Vec<Vec<Word>>,
+    /// Indices into `programs`, by hash of the contents.
+    by_hash: HashMap<u64, Vec<usize>>,
+    /// Index of the program in array 0.
+    current: usize,
+    /// Counted executions, per program and finger.
+    counts: Vec<Vec<u64>>,
+    /// Counted executions per operator.
+    operators: [u64; 14],
+    /// Instructions counted.
+    samples: u64,
+    allocations: u64,
+    allocated_platters: u64,
+    abandons: u64,
+    stack: Vec<Frame>,
+    /// Counted instructions per stack, not including `pending`.
+    stacks: HashMap<Vec<(usize, Word)>, u64>,
+    /// Counted instructions run on the current stack since it last changed.
+    pending: u64,
+}
+
+#[derive(Debug, Copy, Clone)]
+struct Frame {
+    program: usize,
+    /// Where the frame was entered.
+    entry: Word,
+    /// Where a return from it would land: just after its call site.
+    resume: Option<Word>,
+}
+
+impl Profiler {
+    /// Creates a profiler counting every `period`th instruction; 1 counts
+    /// them all.
+    pub fn new(period: u64) -> Profiler {
+        Profiler {
+            period: period.max(1),
+            programs: Vec::new(),
+            by_hash: HashMap::new(),
+            current: 0,
+            counts: Vec::new(),
+            operators: [0; 14],
+            samples: 0,
+            allocations: 0,
+            allocated_platters: 0,
+            abandons: 0,
+            stack: Vec::new(),
+            stacks: HashMap::new(),
+            pending: 0,
+        }
+    }
+
+    /// Starts profiling `program`, running from `finger`.
+    pub(crate) fn start(&mut self, program: &[Word], finger: Word) {
+        self.flush_stack();
+        self.current = self.program_index(program);
+        self.stack = vec![Frame {
+            program: self.current,
+            entry: finger,
+            resume: None,
+        }];
+    }
+
+    /// Records instruction `number`, `inst` at `finger`, which ran with
+    /// `registers` and left the finger at `next` and `program` in array 0.
+    pub(crate) fn record(
+        &mut self,
+        number: u64,
+        finger: Word,
+        inst: Instruction,
+        registers: &[Word; 8],
+        next: Word,
+        program: &[Word],
+    ) {
+        if number.is_multiple_of(self.period) {
+            let counts = &mut self.counts[self.current];
+            let idx = finger as usize;
+            if idx >= counts.len() {
+                counts.resize(idx + 1, 0);
+            }
+            counts[idx] += 1;
+            self.operators[(inst.encode() >> 28) as usize] += 1;
+            self.samples += 1;
+            self.pending += 1;
+        }
+        match inst {
+            Instruction::Allocate { size, .. } => {
+                self.allocations += 1;
+                self.allocated_platters += u64::from(registers[size.idx as usize]);
+            }
+            Instruction::Abandon { .. } => self.abandons += 1,
+            Instruction::LoadProgram { from, .. } if registers[from.idx as usize] != 0 => {
+                self.start(program, next);
+            }
+            Instruction::LoadProgram { .. } => {
+                self.flush_stack();
+                self.jump(finger, next);
+            }
+            _ => {}
+        }
+    }
+
+    /// Index of `program` in `programs`, adding it if it is new.
+    fn program_index(&mut self, program: &[Word]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        program.hash(&mut hasher);
+        let hash = hasher.finish();
+        let known = self.by_hash.get(&hash).and_then(|same_hash| {
+            same_hash
+                .iter()
+                .copied()
+                .find(|&idx| self.programs[idx] == program)
+        });
+        if let Some(idx) = known {
+            return idx;
+        }
+        let idx = self.programs.len();
+        self.by_hash.entry(hash).or_default().push(idx);
+        self.programs.push(program.to_vec());
+        self.counts.push(Vec::new());
+        idx
+    }
+
+    /// Follows a jump within array 0 from `finger` to `target`.
+    fn jump(&mut self, finger: Word, target: Word) {
+        if let Some(idx) = self
+            .stack
+            .iter()
+            .rposition(|frame| frame.resume == Some(target))
+        {
+            self.stack.truncate(idx);
+        } else if let Some(idx) = self.stack.iter().rposition(|frame| frame.entry == target) {
+            self.stack.truncate(idx + 1);
+        } else {
+            if self.stack.len() >= MAX_DEPTH {
+                self.stack.pop();
+            }
+            self.stack.push(Frame {
+                program: self.current,
+                entry: target,
+                resume: Some(finger.wrapping_add(1)),
+            });
+        }
+    }
+
+    /// Charges the instructions run on the current stack to it.
+    fn flush_stack(&mut self) {
+        if self.pending > 0 {
+            *self.stacks.entry(self.stack_key()).or_default() += self.pending;
+            self.pending = 0;
+        }
+    }
+
+    fn stack_key(&self) -> Vec<(usize, Word)> {
+        self.stack
+            .iter()
+            .map(|frame| (frame.program, frame.entry))
+            .collect()
+    }
+
+    /// Writes the hot-spot report.
+    pub fn write_report<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let total = self.samples.max(1) as f64;
+        let percent = |count: u64| 100.0 * count as f64 / total;
+        if self.period > 1 {
+            writeln!(
+                out,
+                "instructions sampled: {} (every {})",
+                self.samples, self.period
+            )?;
+        } else {
+            writeln!(out, "instructions: {}", self.samples)?;
+        }
+        writeln!(
+            out,
+            "arrays allocated: {} ({} platters), abandoned: {}",
+            self.allocations, self.allocated_platters, self.abandons
+        )?;
+        writeln!(out)?;
+        writeln!(out, "programs:")?;
+        for (idx, (program, counts)) in self.programs.iter().zip(&self.counts).enumerate() {
+            let count = counts.iter().sum();
+            writeln!(
+                out,
+                "  {:>4}  {:>8} platters  {:>14}  {:>6.2}%",
+                program_name(idx),
+                program.len(),
+                count,
+                percent(count)
+            )?;
+        }
+        writeln!(out)?;
+        writeln!(out, "operators:")?;
+        let mut operators: Vec<(&str, u64)> = MNEMONICS
+            .iter()
+            .copied()
+            .zip(self.operators.iter().copied())
+            .filter(|&(_, count)| count > 0)
+            .collect();
+        operators.sort_by_key(|&(_, count)| std::cmp::Reverse(count));
+        for (name, count) in operators {
+            writeln!(
+                out,
+                "  {:<12} {:>14}  {:>6.2}%",
+                name,
+                count,
+                percent(count)
+            )?;
+        }
+        writeln!(out)?;
+        writeln!(out, "hot spots:")?;
+        let mut spots: Vec<(u64, usize, Word)> = self
+            .counts
+            .iter()
+            .enumerate()
+            .flat_map(|(program, counts)| {
+                counts
+                    .iter()
+                    .enumerate()
+                    .filter(|&(_, &count)| count > 0)
+                    .map(move |(finger, &count)| (count, program, finger as Word))
+            })
+            .collect();
+        spots.sort_by_key(|&(count, program, finger)| (std::cmp::Reverse(count), program, finger));
+        for (count, program, finger) in spots.into_iter().take(HOT_SPOTS) {
+            let platter = self.programs[program][finger as usize];
+            let text = match Instruction::decode_from(platter) {
+                Ok(inst) => inst.to_string(),
+                Err(_) => ".data".to_string(),
+            };
+            writeln!(
+                out,
+                "  {:>14}  {:>6.2}%  {:>4} {:#010x}: {:08x}  {}",
+                count,
+                percent(count),
+                program_name(program),
+                finger,
+                platter,
+                text
+            )?;
+        }
+        out.flush()
+    }
+
+    /// Writes the stacks in the folded format of flamegraph.pl, one stack
+    /// per line with its frames outermost first, each named after its
+    /// program and entry finger.
+    pub fn write_folded<W: Write>(&self, out: &mut W) -> io::Result<()> {
+        let mut stacks = self.stacks.clone();
+        if self.pending > 0 {
+            *stacks.entry(self.stack_key()).or_default() += self.pending;
+        }
+        let mut stacks: Vec<_> = stacks.into_iter().collect();
+        stacks.sort();
+        for (stack, count) in stacks {
+            let frames: Vec<String> = stack
+                .iter()
+                .map(|&(program, entry)| format!("{}@{:#010x}", program_name(program), entry))
+                .collect();
+            writeln!(out, "{} {}", frames.join(";"), count)?;
+        }
+        out.flush()
+    }
+}
+
+fn program_name(idx: usize) -> String {
+    format!("p{}", idx)
+}
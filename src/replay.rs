@@ -0,0 +1,160 @@
+//! Recording the input a run consumes, and replaying it to rerun exactly
+//! the same execution.
+//!
+//! A log is a sequence of big-endian words: the magic word `0x554d494c`
+//! ("UMIL" in ASCII), the version `1`, then one entry of three words per
+//! `Input`. An entry holds the instruction count at which the input was
+//! read, as its high and then its low word, followed by the byte read or
+//! `0xffffffff` for the end of input.
+//!
+//! Instruction counts come from `Machine::instructions`, so both recording
+//! and replaying need the machine to be driven by `step` or `run_for`.
+
+use crate::console::Console;
+use crate::format::{read_header, read_word, write_word};
+use crate::machine::Word;
+use std::io::{self, BufWriter, ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+
+const MAGIC: Word = u32::from_be_bytes(*b"UMIL");
+const VERSION: Word = 1;
+const END_OF_INPUT: Word = Word::MAX;
+
+/// One `Input`: when it happened and what it read.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Entry {
+    pub instructions: u64,
+    pub byte: Option<u8>,
+}
+
+/// Logs every byte read from the console it wraps.
+pub struct RecordingConsole<W: Write> {
+    inner: Box<dyn Console>,
+    log: BufWriter<W>,
+}
+
+impl<W: Write + Send> RecordingConsole<W> {
+    pub fn new(inner: Box<dyn Console>, log: W) -> io::Result<RecordingConsole<W>> {
+        let mut log = BufWriter::new(log);
+        write_word(&mut log, MAGIC)?;
+        write_word(&mut log, VERSION)?;
+        Ok(RecordingConsole { inner, log })
+    }
+}
+
+impl<W: Write + Send> Console for RecordingConsole<W> {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.read_byte_at(0)
+    }
+
+    fn read_byte_at(&mut self, n: u64) -> io::Result<Option<u8>> {
+        let byte = self.inner.read_byte_at(n)?;
+        write_word(&mut self.log, (n >> 32) as Word)?;
+        write_word(&mut self.log, n as Word)?;
+        write_word(&mut self.log, byte.map_or(END_OF_INPUT, Word::from))?;
+        Ok(byte)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.inner.write_byte(byte)
+    }
+
+    fn write_byte_at(&mut self, n: u64, byte: u8) -> io::Result<()> {
+        self.inner.write_byte_at(n, byte)
+    }
+
+    /// Also flushes the log, so it survives the machine being killed while
+    /// it waits for input.
+    fn flush(&mut self) -> io::Result<()> {
+        self.log.flush()?;
+        self.inner.flush()
+    }
+
+    fn pending_input(&self) -> Vec<u8> {
+        self.inner.pending_input()
+    }
+}
+
+/// Feeds a recorded log back to the machine in place of real input, failing
+/// as soon as the machine reads at a different point than it did when the
+/// log was recorded.
+///
+/// Clones share the log, so a clone kept outside the machine can check
+/// afterwards that all of it was read.
+#[derive(Clone)]
+pub struct ReplayConsole {
+    entries: Arc<Vec<Entry>>,
+    next: Arc<Mutex<usize>>,
+    output: Arc<Mutex<Box<dyn Console>>>,
+}
+
+impl ReplayConsole {
+    /// Replays the log in `input`, passing output through to `output`.
+    pub fn new<R: Read>(input: &mut R, output: Box<dyn Console>) -> io::Result<ReplayConsole> {
+        read_header(input, MAGIC, VERSION, "input log")?;
+        let mut entries = Vec::new();
+        loop {
+            let high = match read_word(input) {
+                Ok(word) => word,
+                Err(err) if err.kind() == ErrorKind::UnexpectedEof => break,
+                Err(err) => return Err(err),
+            };
+            let low = read_word(input)?;
+            let byte = match read_word(input)? {
+                END_OF_INPUT => None,
+                byte => Some(byte as u8),
+            };
+            entries.push(Entry {
+                instructions: (u64::from(high) << 32) | u64::from(low),
+                byte,
+            });
+        }
+        Ok(ReplayConsole {
+            entries: Arc::new(entries),
+            next: Arc::new(Mutex::new(0)),
+            output: Arc::new(Mutex::new(output)),
+        })
+    }
+
+    /// Entries that have not been replayed yet.
+    pub fn remaining(&self) -> &[Entry] {
+        let next = *self.next.lock().unwrap();
+        &self.entries[next..]
+    }
+}
+
+impl Console for ReplayConsole {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.read_byte_at(0)
+    }
+
+    fn read_byte_at(&mut self, n: u64) -> io::Result<Option<u8>> {
+        let mut next = self.next.lock().unwrap();
+        let Some(entry) = self.entries.get(*next) else {
+            return Err(io::Error::other(format!(
+                "replay diverged: input read at instruction {} after the log ran out",
+                n
+            )));
+        };
+        if entry.instructions != n {
+            return Err(io::Error::other(format!(
+                "replay diverged: input {} read at instruction {}, but recorded at {}",
+                *next, n, entry.instructions
+            )));
+        }
+        *next += 1;
+        Ok(entry.byte)
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.output.lock().unwrap().write_byte(byte)
+    }
+
+    fn write_byte_at(&mut self, n: u64, byte: u8) -> io::Result<()> {
+        self.output.lock().unwrap().write_byte_at(n, byte)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.output.lock().unwrap().flush()
+    }
+}
@@ -0,0 +1,267 @@
+//! Driving a program from a script of `expect` and `send` commands, in the
+//! manner of expect(1).
+//!
+//! A script has one command per line; blank lines and lines starting with
+//! `#` are ignored.
+//!
+//! - `expect "<text>"` waits until the program outputs `<text>`.
+//! - `send "<text>"` feeds `<text>` to the program's `Input`.
+//! - `timeout <n>` fails any later `expect` that is not matched within `n`
+//!   instructions of becoming current. `timeout 0` turns timeouts off.
+//!
+//! Text is double-quoted and may contain the escapes `\n`, `\r`, `\t`,
+//! `\\`, `\"`, `\'` and `\xHH`. Output that arrives before an `expect` is
+//! reached still counts towards it, as with expect(1); a match consumes the
+//! output up to its end. Once the script is finished, input and output go to
+//! the console the script wraps.
+
+use crate::console::Console;
+use std::io;
+use std::sync::{Arc, Mutex};
+
+/// Output kept for an `expect` that is not current yet.
+const MAX_SEEN: usize = 1 << 16;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Expect { text: Vec<u8>, timeout: Option<u64> },
+    Send { text: Vec<u8> },
+}
+
+#[derive(Debug, Clone)]
+pub struct Script {
+    pub commands: Vec<Command>,
+}
+
+impl Script {
+    pub fn parse(source: &str) -> Result<Script, String> {
+        let mut commands = Vec::new();
+        let mut timeout = None;
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (keyword, arg) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            let arg = arg.trim();
+            let at_line = |err: String| format!("line {}: {}", i + 1, err);
+            match keyword {
+                "expect" => commands.push(Command::Expect {
+                    text: parse_text(arg).map_err(at_line)?,
+                    timeout,
+                }),
+                "send" => commands.push(Command::Send {
+                    text: parse_text(arg).map_err(at_line)?,
+                }),
+                "timeout" => match arg.parse() {
+                    Ok(0) => timeout = None,
+                    Ok(n) => timeout = Some(n),
+                    Err(_) => return Err(at_line(format!("`{}` is not a number", arg))),
+                },
+                _ => return Err(at_line(format!("unknown command `{}`", keyword))),
+            }
+        }
+        Ok(Script { commands })
+    }
+}
+
+fn parse_text(arg: &str) -> Result<Vec<u8>, String> {
+    match arg.strip_prefix('"').and_then(|arg| arg.strip_suffix('"')) {
+        Some(quoted) => unescape(quoted),
+        None => Err("expected text in double quotes".to_string()),
+    }
+}
+
+/// Decodes the text between a pair of quotes, which may contain the escapes
+/// `\n`, `\r`, `\t`, `\\`, `\"`, `\'` and `\xHH` but no bare `"`.
+pub(crate) fn unescape(quoted: &str) -> Result<Vec<u8>, String> {
+    let mut text = Vec::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        let escaped = match c {
+            '\\' => chars.next(),
+            '"' => return Err("unescaped `\"` in text".to_string()),
+            _ => {
+                let mut utf8 = [0; 4];
+                text.extend(c.encode_utf8(&mut utf8).as_bytes());
+                continue;
+            }
+        };
+        text.push(match escaped {
+            Some('n') => b'\n',
+            Some('r') => b'\r',
+            Some('t') => b'\t',
+            Some('\\') => b'\\',
+            Some('"') => b'"',
+            Some('\'') => b'\'',
+            Some('x') => {
+                let hex: String = chars.by_ref().take(2).collect();
+                // `from_str_radix` alone would also take a sign or one digit.
+                let digits = hex.len() == 2 && hex.chars().all(|c| c.is_ascii_hexdigit());
+                u8::from_str_radix(&hex, 16)
+                    .ok()
+                    .filter(|_| digits)
+                    .ok_or_else(|| format!("bad escape `\\x{}`", hex))?
+            }
+            Some(other) => return Err(format!("unknown escape `\\{}`", other)),
+            None => return Err("text ends with `\\`".to_string()),
+        });
+    }
+    Ok(text)
+}
+
+/// Plays a script against the machine's console.
+///
+/// Clones share their state, so a clone kept outside the machine can check
+/// on timeouts while it runs.
+#[derive(Clone)]
+pub struct ScriptConsole {
+    state: Arc<Mutex<State>>,
+}
+
+struct State {
+    commands: Vec<Command>,
+    /// Index of the current command.
+    next: usize,
+    /// Bytes of the current `send` already read.
+    sent: usize,
+    /// Output since the last match.
+    seen: Vec<u8>,
+    /// Instruction count by which the current `expect` must match.
+    deadline: Option<u64>,
+    inner: Box<dyn Console>,
+}
+
+impl ScriptConsole {
+    pub fn new(script: Script, inner: Box<dyn Console>) -> ScriptConsole {
+        let mut state = State {
+            commands: script.commands,
+            next: 0,
+            sent: 0,
+            seen: Vec::new(),
+            deadline: None,
+            inner,
+        };
+        state.advance(0);
+        ScriptConsole {
+            state: Arc::new(Mutex::new(state)),
+        }
+    }
+
+    /// Instruction count by which the current `expect` must match, if any.
+    pub fn deadline(&self) -> Option<u64> {
+        self.state.lock().unwrap().deadline
+    }
+
+    /// The text of the current `expect`, if the script is waiting on one.
+    pub fn expecting(&self) -> Option<Vec<u8>> {
+        match self.state.lock().unwrap().current() {
+            Some(Command::Expect { text, .. }) => Some(text.clone()),
+            _ => None,
+        }
+    }
+}
+
+impl State {
+    fn current(&self) -> Option<&Command> {
+        self.commands.get(self.next)
+    }
+
+    /// Moves past every `expect` already matched by the output seen so far,
+    /// and every empty `send`, stopping at the first command that has to
+    /// wait. `n` is the instruction count, which starts the clock on an
+    /// `expect`.
+    fn advance(&mut self, n: u64) {
+        self.deadline = None;
+        loop {
+            match self.current() {
+                Some(Command::Expect { text, timeout }) => match find(&self.seen, text) {
+                    Some(end) => {
+                        self.seen.drain(..end);
+                    }
+                    None => {
+                        self.deadline = timeout.map(|timeout| n.saturating_add(timeout));
+                        return;
+                    }
+                },
+                Some(Command::Send { text }) if text.is_empty() => {}
+                _ => return,
+            }
+            self.next += 1;
+        }
+    }
+}
+
+/// End of the first occurrence of `needle` in `haystack`.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    if needle.is_empty() {
+        return Some(0);
+    }
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+        .map(|start| start + needle.len())
+}
+
+impl Console for ScriptConsole {
+    fn read_byte(&mut self) -> io::Result<Option<u8>> {
+        self.read_byte_at(0)
+    }
+
+    fn read_byte_at(&mut self, n: u64) -> io::Result<Option<u8>> {
+        let mut state = self.state.lock().unwrap();
+        let (byte, len) = match state.current() {
+            None => return state.inner.read_byte_at(n),
+            Some(Command::Expect { text, .. }) => {
+                return Err(io::Error::other(format!(
+                    "script: program asked for input while waiting for \"{}\"",
+                    String::from_utf8_lossy(text).escape_debug()
+                )));
+            }
+            Some(Command::Send { text }) => (text[state.sent], text.len()),
+        };
+        state.sent += 1;
+        if state.sent == len {
+            state.next += 1;
+            state.sent = 0;
+            state.advance(n);
+        }
+        Ok(Some(byte))
+    }
+
+    fn write_byte(&mut self, byte: u8) -> io::Result<()> {
+        self.write_byte_at(0, byte)
+    }
+
+    fn write_byte_at(&mut self, n: u64, byte: u8) -> io::Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.inner.write_byte_at(n, byte)?;
+        if state.current().is_none() {
+            return Ok(());
+        }
+        state.seen.push(byte);
+        if let Some(Command::Expect { text, .. }) = state.current() {
+            if state.seen.ends_with(text) {
+                state.seen.clear();
+                state.next += 1;
+                state.advance(n);
+            } else {
+                // Only the tail that could start a match needs keeping.
+                let keep = text.len().saturating_sub(1);
+                let excess = state.seen.len().saturating_sub(keep);
+                state.seen.drain(..excess);
+            }
+        } else if state.seen.len() > MAX_SEEN {
+            state.seen.drain(..MAX_SEEN / 2);
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.state.lock().unwrap().inner.flush()
+    }
+
+    fn pending_input(&self) -> Vec<u8> {
+        self.state.lock().unwrap().inner.pending_input()
+    }
+}
@@ -0,0 +1,35 @@
+//! Notices SIGUSR1, which asks for a snapshot of the running machine.
+
+use an_urgent_appeal::machine::CancelToken;
+use std::os::raw::c_int;
+use std::sync::OnceLock;
+
+#[cfg(target_os = "linux")]
+const SIGUSR1: c_int = 10;
+#[cfg(not(target_os = "linux"))]
+const SIGUSR1: c_int = 30;
+
+/// Cancelled on SIGUSR1, so that the machine stops for the snapshot.
+static TOKEN: OnceLock<CancelToken> = OnceLock::new();
+
+extern "C" {
+    fn signal(signum: c_int, handler: extern "C" fn(c_int)) -> usize;
+}
+
+extern "C" fn on_signal(_: c_int) {
+    if let Some(token) = TOKEN.get() {
+        token.cancel();
+    }
+}
+
+/// Cancels `token` whenever SIGUSR1 arrives from now on.
+pub fn install(token: CancelToken) {
+    if TOKEN.set(token).is_err() {
+        panic!("signal handler installed twice");
+    }
+    // The handler only loads the token, which is set by now, and stores to
+    // an atomic, all of which is async-signal-safe, so it is sound.
+    unsafe {
+        signal(SIGUSR1, on_signal);
+    }
+}
@@ -0,0 +1,64 @@
+//! Counters summarising a run, for comparing changes to the machine.
+
+use crate::instructions::{Instruction, MNEMONICS};
+use std::io::{self, Write};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// Instructions executed, including one that faulted.
+    pub instructions: u64,
+    /// Instructions executed per operator.
+    pub operators: [u64; 14],
+    /// Most arrays other than array 0 live at once.
+    pub peak_arrays: usize,
+    /// Most platters in live arrays other than array 0 at once.
+    pub peak_platters: u64,
+    /// `LoadProgram`s that replaced array 0 with another array.
+    pub loads_other: u64,
+    /// `LoadProgram`s that jumped within array 0.
+    pub loads_in_place: u64,
+}
+
+impl Stats {
+    /// Starts counting with `arrays` live arrays holding `platters` platters.
+    pub(crate) fn new(arrays: usize, platters: u64) -> Stats {
+        Stats {
+            peak_arrays: arrays,
+            peak_platters: platters,
+            ..Stats::default()
+        }
+    }
+
+    pub(crate) fn executed(&mut self, inst: &Instruction) {
+        self.instructions += 1;
+        self.operators[(inst.encode() >> 28) as usize] += 1;
+    }
+
+    /// Notes that `arrays` arrays holding `platters` platters are live.
+    pub(crate) fn allocated(&mut self, arrays: usize, platters: u64) {
+        self.peak_arrays = self.peak_arrays.max(arrays);
+        self.peak_platters = self.peak_platters.max(platters);
+    }
+
+    /// Writes a summary of a run that took `elapsed`.
+    pub fn write_summary<W: Write>(&self, out: &mut W, elapsed: Duration) -> io::Result<()> {
+        let seconds = elapsed.as_secs_f64();
+        writeln!(out, "instructions: {}", self.instructions)?;
+        for (name, count) in MNEMONICS.iter().zip(&self.operators) {
+            writeln!(out, "  {:<12} {:>14}", name, count)?;
+        }
+        writeln!(out, "peak live arrays: {}", self.peak_arrays)?;
+        writeln!(out, "peak live platters: {}", self.peak_platters)?;
+        writeln!(
+            out,
+            "loadprog: {} from other arrays, {} in place",
+            self.loads_other, self.loads_in_place
+        )?;
+        writeln!(out, "wall time: {:.3}s", seconds)?;
+        if seconds > 0.0 {
+            writeln!(out, "MIPS: {:.2}", self.instructions as f64 / seconds / 1e6)?;
+        }
+        out.flush()
+    }
+}
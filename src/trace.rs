@@ -0,0 +1,166 @@
+//! Logging each instruction the machine executes.
+//!
+//! A trace records, for every instruction that completes, its number
+//! counting from 1, its finger, its platter and the registers before and
+//! after it. Instructions that fault are left to the crash dump.
+//!
+//! In the text format each instruction is one line: the number, the finger,
+//! the platter and its mnemonic, the eight registers before it, and then
+//! after `->` the registers it changed, as in
+//!
+//! ```text
+//! 12 0x0000002a: 3000004b  add r1 <- r1, r3             00000000 ffffffff 00000000 87654321 00000000 00000000 00000000 00000000 -> r1=87654320
+//! ```
+//!
+//! The binary format is a sequence of big-endian words: the magic word
+//! `0x554d5452` ("UMTR" in ASCII), the version `1`, then a record of 20
+//! words per instruction. A record holds the instruction's number as its
+//! high and then its low word, the finger, the platter, then `r0` to `r7`
+//! before and `r0` to `r7` after.
+//!
+//! Tracing runs every instruction in the interpreter, so it is much slower
+//! than running untraced.
+
+use crate::format::write_word;
+use crate::instructions::Instruction;
+use crate::machine::Word;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+
+const MAGIC: Word = u32::from_be_bytes(*b"UMTR");
+const VERSION: Word = 1;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Binary,
+}
+
+/// Which instructions to trace. An instruction is traced if it passes every
+/// test; an empty list passes everything.
+#[derive(Debug, Clone, Default)]
+pub struct Filter {
+    /// Fingers to trace.
+    pub fingers: Vec<Range<Word>>,
+    /// Operator numbers to trace, 0 to 13.
+    pub operators: Vec<Word>,
+    /// Instruction numbers to trace.
+    pub window: Option<Range<u64>>,
+}
+
+impl Filter {
+    pub fn matches(&self, number: u64, finger: Word, platter: Word) -> bool {
+        (self.fingers.is_empty() || self.fingers.iter().any(|range| range.contains(&finger)))
+            && (self.operators.is_empty() || self.operators.contains(&(platter >> 28)))
+            && self
+                .window
+                .as_ref()
+                .is_none_or(|window| window.contains(&number))
+    }
+}
+
+/// Writes a trace of the instructions that pass its filter.
+///
+/// Writing happens in the middle of execution, where errors cannot be
+/// returned, so the first error stops the trace and is kept for `finish`.
+pub struct Tracer {
+    out: BufWriter<Box<dyn Write + Send>>,
+    format: Format,
+    filter: Filter,
+    error: Option<io::Error>,
+}
+
+impl Tracer {
+    pub fn new(out: Box<dyn Write + Send>, format: Format, filter: Filter) -> io::Result<Tracer> {
+        let mut out = BufWriter::new(out);
+        if format == Format::Binary {
+            write_word(&mut out, MAGIC)?;
+            write_word(&mut out, VERSION)?;
+        }
+        Ok(Tracer {
+            out,
+            format,
+            filter,
+            error: None,
+        })
+    }
+
+    /// Whether instruction `number`, at `finger`, should be traced.
+    pub fn wants(&self, number: u64, finger: Word, platter: Word) -> bool {
+        self.error.is_none() && self.filter.matches(number, finger, platter)
+    }
+
+    /// Records an instruction that `wants` accepted.
+    pub fn record(
+        &mut self,
+        number: u64,
+        finger: Word,
+        platter: Word,
+        instruction: Instruction,
+        before: &[Word; 8],
+        after: &[Word; 8],
+    ) {
+        let written = match self.format {
+            Format::Text => self.write_text(number, finger, platter, instruction, before, after),
+            Format::Binary => self.write_binary(number, finger, platter, before, after),
+        };
+        if let Err(err) = written {
+            self.error = Some(err);
+        }
+    }
+
+    fn write_text(
+        &mut self,
+        number: u64,
+        finger: Word,
+        platter: Word,
+        instruction: Instruction,
+        before: &[Word; 8],
+        after: &[Word; 8],
+    ) -> io::Result<()> {
+        write!(
+            self.out,
+            "{} {:#010x}: {:08x}  {:<28}",
+            number,
+            finger,
+            platter,
+            instruction.to_string()
+        )?;
+        for reg in before {
+            write!(self.out, " {:08x}", reg)?;
+        }
+        write!(self.out, " ->")?;
+        for (idx, (old, new)) in before.iter().zip(after).enumerate() {
+            if old != new {
+                write!(self.out, " r{}={:08x}", idx, new)?;
+            }
+        }
+        writeln!(self.out)
+    }
+
+    fn write_binary(
+        &mut self,
+        number: u64,
+        finger: Word,
+        platter: Word,
+        before: &[Word; 8],
+        after: &[Word; 8],
+    ) -> io::Result<()> {
+        write_word(&mut self.out, (number >> 32) as Word)?;
+        write_word(&mut self.out, number as Word)?;
+        write_word(&mut self.out, finger)?;
+        write_word(&mut self.out, platter)?;
+        for &reg in before.iter().chain(after) {
+            write_word(&mut self.out, reg)?;
+        }
+        Ok(())
+    }
+
+    /// Flushes the trace, reporting the first error met while writing it.
+    pub fn finish(mut self) -> io::Result<()> {
+        match self.error.take() {
+            Some(err) => Err(err),
+            None => self.out.flush(),
+        }
+    }
+}
@@ -1,3 +0,0 @@
-pub mod errors;
-pub mod instructions;
-pub mod machine;
@@ -0,0 +1,178 @@
+//! The assembler, checked by decoding or running what it produces.
+
+use an_urgent_appeal::asm::assemble;
+use an_urgent_appeal::console::BufferConsole;
+use an_urgent_appeal::instructions::{In, Instruction, Out};
+use an_urgent_appeal::machine::{ExitReason, Machine, Word};
+
+fn decode(scroll: &[Word]) -> Vec<Instruction> {
+    scroll
+        .iter()
+        .map(|&platter| Instruction::decode_from(platter).unwrap())
+        .collect()
+}
+
+/// Runs `scroll` to its `halt` and returns the registers it left.
+fn run(scroll: &[Word]) -> [Word; 8] {
+    let program = scroll.iter().flat_map(|word| word.to_be_bytes()).collect();
+    let mut m = Machine::with_console(program, Box::new(BufferConsole::default()));
+    assert!(matches!(m.execute(), ExitReason::Halted));
+    *m.registers()
+}
+
+fn error(source: &str) -> (usize, String) {
+    let err = assemble(source).unwrap_err();
+    (err.line, err.message)
+}
+
+#[test]
+fn labels_may_be_used_before_they_are_defined() {
+    let scroll = assemble(
+        "        orthography r1 <- data\n\
+         \x20       index r2 <- r0[r1]\n\
+         \x20       halt\n\
+         data:   .word 1234\n",
+    )
+    .unwrap();
+    assert_eq!(
+        decode(&scroll[..1]),
+        [Instruction::LoadRegister {
+            dest: Out::new(1),
+            val: 3,
+        }]
+    );
+    assert_eq!(run(&scroll)[2], 1234);
+}
+
+#[test]
+fn constants_stand_for_their_values() {
+    let scroll = assemble(
+        ".const ANSWER = 42\n\
+         .const NL = '\\n'\n\
+         .word ANSWER, NL\n",
+    )
+    .unwrap();
+    assert_eq!(scroll, [42, 10]);
+}
+
+#[test]
+fn constants_must_be_defined_before_use() {
+    let (line, message) = error(".word LATER\n.const LATER = 1\n");
+    assert_eq!(line, 1);
+    assert!(message.contains("LATER"), "{}", message);
+}
+
+#[test]
+fn constants_and_labels_share_names() {
+    let (line, message) = error(".const here = 1\nhere: halt\n");
+    assert_eq!((line, message.as_str()), (2, "`here` is already defined"));
+}
+
+#[test]
+fn each_expansion_gets_its_own_labels() {
+    let scroll = assemble(
+        ".macro skip\n\
+         \x20       jmp over\\@, r6, r7\n\
+         \x20       .word 0xdead\n\
+         over\\@:\n\
+         .endm\n\
+         \x20       skip\n\
+         \x20       skip\n\
+         \x20       halt\n",
+    )
+    .unwrap();
+    assert_eq!(scroll.len(), 9);
+    let targets: Vec<Word> = decode(&scroll[..8])
+        .into_iter()
+        .filter_map(|inst| match inst {
+            Instruction::LoadRegister { dest, val } if dest == Out::new(7) => Some(val),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(targets, [4, 8]);
+    run(&scroll);
+}
+
+#[test]
+fn errors_in_nested_macros_name_the_outermost() {
+    let (line, message) = error(
+        ".macro inner\n\
+         \x20       bogus r1\n\
+         .endm\n\
+         .macro outer\n\
+         \x20       inner\n\
+         .endm\n\
+         \x20       halt\n\
+         \x20       outer\n",
+    );
+    assert_eq!(line, 8);
+    assert!(message.starts_with("in macro `outer`: "), "{}", message);
+    assert!(!message.contains("inner"), "{}", message);
+}
+
+#[test]
+fn recursive_macros_are_stopped() {
+    let (line, message) = error(".macro again\n again\n.endm\n again\n");
+    assert_eq!(line, 4);
+    assert!(message.ends_with("expands too deeply"), "{}", message);
+}
+
+#[test]
+fn macro_arguments_split_outside_quotes() {
+    let source = ".macro emit text, reg\n\
+                  \x20       .string \\text\n\
+                  \x20       orthography \\reg <- 1\n\
+                  .endm\n";
+    let scroll = assemble(&format!("{}  emit \"é, ;\", r1 ; comment, here\n", source)).unwrap();
+    let mut expected: Vec<Word> = "é, ;".bytes().map(Word::from).collect();
+    expected.push(
+        Instruction::LoadRegister {
+            dest: Out::new(1),
+            val: 1,
+        }
+        .encode(),
+    );
+    assert_eq!(scroll, expected);
+}
+
+#[test]
+fn li_uses_one_orthography_for_small_values() {
+    for value in [0, 1, 0x01ff_ffff] {
+        let scroll = assemble(&format!("li r1 <- {}\nhalt\n", value)).unwrap();
+        assert_eq!(scroll.len(), 2, "{:#x}", value);
+        assert_eq!(run(&scroll)[1], value);
+    }
+}
+
+#[test]
+fn li_complements_values_near_the_top() {
+    for value in [0xffff_ffff, 0xfe00_0000, 0xfe12_3456] {
+        let scroll = assemble(&format!("li r1 <- {}\nhalt\n", value)).unwrap();
+        assert_eq!(scroll.len(), 3, "{:#x}", value);
+        assert_eq!(run(&scroll)[1], value);
+    }
+}
+
+#[test]
+fn li_builds_other_values_with_a_scratch_register() {
+    for value in [0x0200_0000, 0x1234_5678, 0x8765_4321, 0xfdff_ffff] {
+        let scroll = assemble(&format!("li r1 <- {}, r2\nhalt\n", value)).unwrap();
+        assert_eq!(scroll.len(), 6, "{:#x}", value);
+        assert_eq!(run(&scroll)[1], value);
+    }
+    let (_, message) = error("li r1 <- 0x12345678\n");
+    assert!(message.contains("scratch"), "{}", message);
+}
+
+#[test]
+fn registers_are_checked() {
+    assert_eq!(
+        decode(&assemble("output r7\n").unwrap()),
+        [Instruction::Output { val: In::new(7) }]
+    );
+    let (line, message) = error("halt\noutput r8\n");
+    assert_eq!(
+        (line, message.as_str()),
+        (2, "expected a register, found `r8`")
+    );
+}
@@ -0,0 +1,73 @@
+//! Programs that rewrite themselves run the new instructions, not the ones
+//! decoded before.
+
+use an_urgent_appeal::asm::assemble;
+use an_urgent_appeal::console::BufferConsole;
+use an_urgent_appeal::machine::{Machine, StepResult};
+
+/// Runs `source` in the interpreter, which caches what it decodes, and
+/// returns the sum it leaves in r4.
+fn sum(source: &str) -> u32 {
+    let program = assemble(source)
+        .unwrap()
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect();
+    let mut m = Machine::with_console(program, Box::new(BufferConsole::default()));
+    // Stale instructions would loop, so stop well short of forever.
+    assert!(matches!(m.run_for(1000), StepResult::Halted));
+    m.registers()[4]
+}
+
+#[test]
+fn amending_array_0_replaces_a_decoded_instruction() {
+    // Adds what `target` loads, then rewrites it to load 5 and goes round
+    // again, so a stale decode would add 1 + 1.
+    let source = "        li r3 <- 0xd2000005, r5     ; orthography r1 <- 5
+                  \x20       orthography r2 <- 2
+                  loop:
+                  target: orthography r1 <- 1
+                  \x20       add r4 <- r4, r1
+                  \x20       orthography r7 <- target
+                  \x20       amend r0[r7] <- r3
+                  \x20       li r5 <- 0xffffffff
+                  \x20       add r2 <- r2, r5
+                  \x20       orthography r5 <- done
+                  \x20       orthography r7 <- loop
+                  \x20       cmov r5 <- r7 if r2
+                  \x20       loadprog r0, r5
+                  done:   halt\n";
+    assert_eq!(sum(source), 6);
+}
+
+#[test]
+fn loading_another_array_replaces_every_decoded_instruction() {
+    // Runs `target` from array 0, then loads a program with other
+    // instructions at the same offsets and runs them from there, so a stale
+    // decode would add 1 and go back to `build`.
+    let source = "        orthography r6 <- build
+                  \x20       orthography r7 <- target
+                  \x20       loadprog r0, r7
+                  target: orthography r1 <- 1
+                  \x20       add r4 <- r4, r1
+                  \x20       loadprog r0, r6
+                  build:  orthography r1 <- 32
+                  \x20       alloc r2 <- r1
+                  \x20       orthography r3 <- patch
+                  \x20       orthography r1 <- 1
+                  \x20       index r5 <- r0[r3]
+                  \x20       amend r2[r7] <- r5
+                  \x20       add r3 <- r3, r1
+                  \x20       add r7 <- r7, r1
+                  \x20       index r5 <- r0[r3]
+                  \x20       amend r2[r7] <- r5
+                  \x20       add r3 <- r3, r1
+                  \x20       add r7 <- r7, r1
+                  \x20       index r5 <- r0[r3]
+                  \x20       amend r2[r7] <- r5
+                  \x20       orthography r7 <- target
+                  \x20       loadprog r2, r7
+                  ; orthography r1 <- 5, add r4 <- r4, r1, halt
+                  patch:  .word 0xd2000005, 0x30000121, 0x70000000\n";
+    assert_eq!(sum(source), 6);
+}
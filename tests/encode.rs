@@ -0,0 +1,90 @@
+//! `Instruction::encode` against `Instruction::decode_from`.
+
+use an_urgent_appeal::instructions::{Instruction, Out};
+use an_urgent_appeal::machine::Word;
+
+/// Bits of a platter that the spec gives a meaning to for operator `op`.
+fn defined_bits(op: Word) -> Word {
+    let registers = match op {
+        // Halt takes no registers.
+        7 => 0,
+        // Allocate and LoadProgram take B and C.
+        8 | 12 => 0o77,
+        // Abandon, Output and Input take C.
+        9..=11 => 0o7,
+        // Orthography has its own layout: A in bits 25 to 27, then the value.
+        13 => 0x0fff_ffff,
+        _ => 0o777,
+    };
+    0xf000_0000 | registers
+}
+
+/// A xorshift generator, so the sampled words are the same on every run.
+fn words(count: usize) -> impl Iterator<Item = Word> {
+    let mut state: Word = 0x2006_1cfc;
+    (0..count).map(move |_| {
+        state ^= state << 13;
+        state ^= state >> 17;
+        state ^= state << 5;
+        state
+    })
+}
+
+#[test]
+fn every_standard_instruction_round_trips() {
+    for op in 0..13 {
+        for registers in 0..0o1000 {
+            let word = op << 28 | registers;
+            let inst = Instruction::decode_from(word).unwrap();
+            assert_eq!(Instruction::decode_from(inst.encode()).unwrap(), inst);
+            assert_eq!(inst.encode(), word & defined_bits(op), "{:#010x}", word);
+        }
+    }
+}
+
+#[test]
+fn orthography_round_trips() {
+    let edges = [
+        0,
+        1,
+        0x7f,
+        0xffff,
+        0x10000,
+        0x00ff_ffff,
+        0x01ff_fffe,
+        0x01ff_ffff,
+    ];
+    for dest in 0..8 {
+        let values = edges.iter().copied().chain(words(4096).map(|w| w >> 7));
+        for value in values {
+            let word = 13 << 28 | dest << 25 | value;
+            let inst = Instruction::decode_from(word).unwrap();
+            assert_eq!(
+                inst,
+                Instruction::LoadRegister {
+                    dest: Out::new(dest as u8),
+                    val: value,
+                }
+            );
+            assert_eq!(inst.encode(), word);
+        }
+    }
+}
+
+#[test]
+fn encoding_keeps_every_defined_bit() {
+    for word in words(1 << 16) {
+        let op = word >> 28;
+        match Instruction::decode_from(word) {
+            Ok(inst) => assert_eq!(inst.encode(), word & defined_bits(op), "{:#010x}", word),
+            Err(_) => assert!(op >= 14, "{:#010x}", word),
+        }
+    }
+}
+
+#[test]
+fn unknown_operators_do_not_decode() {
+    for word in [0xe000_0000, 0xefff_ffff, 0xf000_0000, 0xffff_ffff] {
+        assert!(Instruction::decode_from(word).is_err());
+    }
+}
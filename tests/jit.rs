@@ -0,0 +1,149 @@
+//! Native code, checked against the interpreter running the same programs.
+
+#![cfg(feature = "jit")]
+
+use an_urgent_appeal::asm::assemble;
+use an_urgent_appeal::console::BufferConsole;
+use an_urgent_appeal::errors::UmError;
+use an_urgent_appeal::limits::Limits;
+use an_urgent_appeal::machine::{CancelToken, ExitReason, Machine};
+use std::thread;
+use std::time::Duration;
+
+fn machine(source: &str) -> (Machine, BufferConsole) {
+    let program = assemble(source)
+        .unwrap()
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect();
+    let console = BufferConsole::default();
+    (
+        Machine::with_console(program, Box::new(console.clone())),
+        console,
+    )
+}
+
+/// A machine that runs every instruction in the interpreter, which an
+/// instruction limit forces.
+fn interpreting(source: &str) -> (Machine, BufferConsole) {
+    let (mut m, console) = machine(source);
+    m.set_limits(Limits {
+        instructions: Some(u64::MAX),
+        ..Limits::default()
+    });
+    (m, console)
+}
+
+/// The fault `source` stops with, first from native code, then from the
+/// interpreter alone.
+fn faults(source: &str) -> [String; 2] {
+    [machine(source), interpreting(source)].map(|(mut m, _)| match m.execute() {
+        ExitReason::Faulted(fault) => fault.to_string(),
+        reason => panic!("expected a fault, got {:?}", reason),
+    })
+}
+
+#[test]
+fn amending_a_compiled_block_recompiles_it() {
+    // Each pass adds what `target` loads, then rewrites it to load one
+    // more, so stale code would add 1 + 2 + 2.
+    let source = "        li r3 <- 0xd2000001, r5     ; orthography r1 <- 1
+                  \x20       orthography r6 <- 3
+                  loop:
+                  target: orthography r1 <- 1
+                  \x20       add r4 <- r4, r1
+                  \x20       orthography r5 <- 1
+                  \x20       add r3 <- r3, r5
+                  \x20       orthography r7 <- target
+                  \x20       amend r0[r7] <- r3
+                  \x20       li r5 <- 0xffffffff
+                  \x20       add r6 <- r6, r5
+                  \x20       orthography r5 <- done
+                  \x20       orthography r7 <- loop
+                  \x20       cmov r5 <- r7 if r6
+                  \x20       loadprog r0, r5
+                  done:   halt\n";
+    for (mut m, _) in [machine(source), interpreting(source)] {
+        assert!(matches!(m.execute(), ExitReason::Halted));
+        assert_eq!(m.registers()[4], 6);
+    }
+}
+
+#[test]
+fn division_by_zero_in_native_code_is_reported_by_the_interpreter() {
+    let [native, interpreted] = faults(
+        "        orthography r1 <- 7
+         \x20       div r2 <- r1, r0
+         \x20       halt\n",
+    );
+    assert_eq!(native, interpreted);
+    assert!(native.starts_with(&UmError::DivideByZero.to_string()));
+}
+
+#[test]
+fn array_faults_in_native_code_are_reported_by_the_interpreter() {
+    let sources = [
+        // Past the end of array 0.
+        "        orthography r1 <- 100
+         \x20       index r2 <- r0[r1]
+         \x20       halt\n",
+        // Past the end of an allocated array.
+        "        orthography r1 <- 2
+         \x20       alloc r2 <- r1
+         \x20       orthography r3 <- 5
+         \x20       amend r2[r3] <- r1
+         \x20       halt\n",
+        // An array that was never allocated.
+        "        orthography r1 <- 9
+         \x20       abandon r1
+         \x20       halt\n",
+    ];
+    for source in sources {
+        let [native, interpreted] = faults(source);
+        assert_eq!(native, interpreted);
+    }
+}
+
+#[test]
+fn a_native_loop_can_be_cancelled() {
+    let (mut m, _) = machine("loop:   loadprog r0, r0\n");
+    let token = CancelToken::new();
+    m.set_cancel_token(token.clone());
+    thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        token.cancel();
+    });
+    assert!(matches!(m.execute(), ExitReason::Cancelled));
+    // Every jump ran natively; the interpreter counts only what it runs.
+    assert_eq!(m.instructions(), 0);
+}
+
+#[test]
+fn native_code_counts_what_the_interpreter_counts() {
+    let source = "        orthography r7 <- 3
+                  \x20       li r6 <- 0xffffffff
+                  loop:   orthography r1 <- 'a'
+                  \x20       add r1 <- r1, r7
+                  \x20       output r1
+                  \x20       orthography r2 <- 4
+                  \x20       alloc r3 <- r2
+                  \x20       orthography r4 <- 1
+                  \x20       amend r3[r4] <- r1
+                  \x20       index r1 <- r3[r4]
+                  \x20       abandon r3
+                  \x20       mul r1 <- r1, r4
+                  \x20       nand r1 <- r1, r1
+                  \x20       add r7 <- r7, r6
+                  \x20       orthography r4 <- done
+                  \x20       orthography r5 <- loop
+                  \x20       cmov r4 <- r5 if r7
+                  \x20       loadprog r0, r4
+                  done:   halt\n";
+    let [native, interpreted] = [machine(source), interpreting(source)].map(|(mut m, console)| {
+        m.enable_stats();
+        assert!(matches!(m.execute(), ExitReason::Halted));
+        (format!("{:?}", m.stats().unwrap()), console.output())
+    });
+    assert_eq!(native, interpreted);
+    assert_eq!(native.1, b"dcb");
+}
@@ -0,0 +1,33 @@
+//! Bytes written by `Output` reach the console unchanged.
+
+use an_urgent_appeal::console::BufferConsole;
+use an_urgent_appeal::instructions::{In, Instruction, Out};
+use an_urgent_appeal::machine::{ExitReason, Machine, Word};
+
+/// A program that outputs every value from 0 to 255 in order, then halts.
+fn every_byte() -> Vec<u8> {
+    let mut program: Vec<Instruction> = (0..=255)
+        .flat_map(|val: Word| {
+            vec![
+                Instruction::LoadRegister {
+                    dest: Out::new(1),
+                    val,
+                },
+                Instruction::Output { val: In::new(1) },
+            ]
+        })
+        .collect();
+    program.push(Instruction::Halt);
+    program
+        .iter()
+        .flat_map(|inst| inst.encode().to_be_bytes())
+        .collect()
+}
+
+#[test]
+fn every_byte_is_output_raw() {
+    let console = BufferConsole::default();
+    let mut m = Machine::with_console(every_byte(), Box::new(console.clone()));
+    assert!(matches!(m.execute(), ExitReason::Halted));
+    assert_eq!(console.output(), (0..=255).collect::<Vec<u8>>());
+}
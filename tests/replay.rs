@@ -0,0 +1,142 @@
+//! Recorded input replays to the same run, and replays that go another way
+//! are caught.
+
+use an_urgent_appeal::asm::assemble;
+use an_urgent_appeal::console::{BufferConsole, Console};
+use an_urgent_appeal::machine::{Machine, StepResult, Word};
+use an_urgent_appeal::replay::{Entry, RecordingConsole, ReplayConsole};
+use std::io::{self, ErrorKind, Write};
+use std::sync::{Arc, Mutex};
+
+/// Echoes two bytes, then reads the end of input.
+const ECHO: &str = "        input r1
+                    \x20       output r1
+                    \x20       add r2 <- r2, r1
+                    \x20       input r1
+                    \x20       output r1
+                    \x20       input r3
+                    \x20       halt\n";
+
+/// A log that can still be read once the machine owns the console.
+#[derive(Clone, Default)]
+struct Log(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Log {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.lock().unwrap().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+fn program(source: &str) -> Vec<u8> {
+    assemble(source)
+        .unwrap()
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect()
+}
+
+/// Runs `source` on `console`, counting every instruction as recording and
+/// replaying need.
+fn run(source: &str, console: Box<dyn Console>) -> Machine {
+    let mut m = Machine::with_console(program(source), console);
+    let result = m.run_for(u64::MAX);
+    m.flush_console().unwrap();
+    match result {
+        StepResult::Halted | StepResult::Fault(_) => m,
+        result => panic!("unexpected {:?}", result),
+    }
+}
+
+fn record(source: &str, input: &[u8]) -> Vec<u8> {
+    let log = Log::default();
+    let console = RecordingConsole::new(Box::new(BufferConsole::new(input)), log.clone()).unwrap();
+    run(source, Box::new(console));
+    let bytes = log.0.lock().unwrap().clone();
+    bytes
+}
+
+fn replay(log: &[u8]) -> (ReplayConsole, BufferConsole) {
+    let output = BufferConsole::default();
+    let replay = ReplayConsole::new(&mut &log[..], Box::new(output.clone())).unwrap();
+    (replay, output)
+}
+
+/// The fault that replaying `log` to `source` stops with.
+fn diverges(source: &str, log: &[u8]) -> String {
+    let (replay, _) = replay(log);
+    let mut m = Machine::with_console(program(source), Box::new(replay));
+    match m.run_for(u64::MAX) {
+        StepResult::Fault(fault) => fault.to_string(),
+        result => panic!("expected a fault, got {:?}", result),
+    }
+}
+
+#[test]
+fn a_recording_replays_to_the_same_run() {
+    let log = record(ECHO, b"ab");
+    let (console, output) = replay(&log);
+    assert_eq!(
+        console.remaining(),
+        [
+            Entry {
+                instructions: 1,
+                byte: Some(b'a'),
+            },
+            Entry {
+                instructions: 4,
+                byte: Some(b'b'),
+            },
+            Entry {
+                instructions: 6,
+                byte: None,
+            },
+        ]
+    );
+    let m = run(ECHO, Box::new(console.clone()));
+    assert_eq!(output.output(), b"ab");
+    assert_eq!(m.registers()[2..4], [Word::from(b'a'), Word::MAX]);
+    assert!(console.remaining().is_empty());
+}
+
+#[test]
+fn reading_at_another_instruction_diverges() {
+    let log = record(ECHO, b"ab");
+    let fault = diverges(&format!("        orthography r4 <- 0\n{}", ECHO), &log);
+    assert!(
+        fault.contains("replay diverged: input 0 read at instruction 2, but recorded at 1"),
+        "{}",
+        fault
+    );
+}
+
+#[test]
+fn reading_past_the_log_diverges() {
+    let log = record("input r1\nhalt\n", b"a");
+    let fault = diverges(ECHO, &log);
+    assert!(fault.contains("after the log ran out"), "{}", fault);
+}
+
+#[test]
+fn malformed_logs_are_rejected() {
+    let header = |magic: &[u8; 4], version: Word| {
+        let mut bytes = magic.to_vec();
+        bytes.extend(version.to_be_bytes());
+        bytes
+    };
+    let error = |bytes: &[u8]| {
+        ReplayConsole::new(&mut &bytes[..], Box::new(BufferConsole::default()))
+            .err()
+            .expect("malformed log accepted")
+            .kind()
+    };
+    assert_eq!(error(&header(b"UMSS", 1)), ErrorKind::InvalidData);
+    assert_eq!(error(&header(b"UMIL", 2)), ErrorKind::InvalidData);
+    assert_eq!(error(&header(b"UMIL", 1)[..6]), ErrorKind::UnexpectedEof);
+    let mut truncated = header(b"UMIL", 1);
+    truncated.extend([0; 8]);
+    assert_eq!(error(&truncated), ErrorKind::UnexpectedEof);
+}
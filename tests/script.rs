@@ -0,0 +1,125 @@
+//! Parsing expect scripts and playing them against a program's output.
+
+use an_urgent_appeal::console::{BufferConsole, Console};
+use an_urgent_appeal::script::{Command, Script, ScriptConsole};
+
+fn parse(source: &str) -> Vec<Command> {
+    Script::parse(source).unwrap().commands
+}
+
+fn error(source: &str) -> String {
+    Script::parse(source).unwrap_err()
+}
+
+fn console(source: &str) -> (ScriptConsole, BufferConsole) {
+    let inner = BufferConsole::new(b"typed");
+    let script = ScriptConsole::new(Script::parse(source).unwrap(), Box::new(inner.clone()));
+    (script, inner)
+}
+
+fn write(console: &mut ScriptConsole, n: u64, text: &[u8]) {
+    for &byte in text {
+        console.write_byte_at(n, byte).unwrap();
+    }
+}
+
+#[test]
+fn commands_are_parsed_with_the_timeout_in_force() {
+    let commands = parse(
+        "# logs in\n\
+         \n\
+         expect \"login: \"\n\
+         timeout 100\n\
+         \x20 send \"guest\\n\"  \n\
+         expect \"$\"\n\
+         timeout 0\n\
+         expect \"\"\n",
+    );
+    assert_eq!(
+        commands,
+        [
+            Command::Expect {
+                text: b"login: ".to_vec(),
+                timeout: None,
+            },
+            Command::Send {
+                text: b"guest\n".to_vec(),
+            },
+            Command::Expect {
+                text: b"$".to_vec(),
+                timeout: Some(100),
+            },
+            Command::Expect {
+                text: Vec::new(),
+                timeout: None,
+            },
+        ]
+    );
+}
+
+#[test]
+fn escapes_are_decoded() {
+    let commands = parse(r#"send "\n\r\t\\\"\'\x00\xfFé""#);
+    let mut text = b"\n\r\t\\\"'\x00\xff".to_vec();
+    text.extend("é".bytes());
+    assert_eq!(commands, [Command::Send { text }]);
+}
+
+#[test]
+fn malformed_lines_are_reported_with_their_number() {
+    assert_eq!(error("\nwait \"x\"\n"), "line 2: unknown command `wait`");
+    assert_eq!(error("send x\n"), "line 1: expected text in double quotes");
+    assert_eq!(error("timeout soon\n"), "line 1: `soon` is not a number");
+    assert_eq!(error(r#"send "a"b""#), "line 1: unescaped `\"` in text");
+    assert_eq!(error(r#"send "\q""#), "line 1: unknown escape `\\q`");
+    assert_eq!(error(r#"send "\""#), "line 1: text ends with `\\`");
+}
+
+#[test]
+fn hex_escapes_take_exactly_two_digits() {
+    assert_eq!(error(r#"send "\x+1""#), "line 1: bad escape `\\x+1`");
+    assert_eq!(error(r#"send "\x4""#), "line 1: bad escape `\\x4`");
+    assert_eq!(error(r#"send "\xg0""#), "line 1: bad escape `\\xg0`");
+}
+
+#[test]
+fn expected_text_matches_across_writes() {
+    let (mut script, inner) = console("expect \"hello\"\nsend \"x\"\n");
+    write(&mut script, 1, b"hel");
+    assert_eq!(script.expecting(), Some(b"hello".to_vec()));
+    write(&mut script, 2, b"hehello");
+    assert_eq!(script.expecting(), None);
+    assert_eq!(script.read_byte_at(3).unwrap(), Some(b'x'));
+    // Past the end of the script, input comes from the console it wraps.
+    assert_eq!(script.read_byte_at(4).unwrap(), Some(b't'));
+    assert_eq!(inner.output(), b"helhehello");
+}
+
+#[test]
+fn output_before_an_expect_counts_towards_it() {
+    let (mut script, _) = console("send \"a\"\nexpect \"ok\"\nexpect \"ok\"\n");
+    write(&mut script, 1, b"ok, ok");
+    assert_eq!(script.expecting(), None);
+    assert_eq!(script.read_byte_at(2).unwrap(), Some(b'a'));
+    assert_eq!(script.expecting(), None);
+}
+
+#[test]
+fn input_is_refused_while_output_is_expected() {
+    let (mut script, _) = console("expect \"prompt\"\n");
+    let err = script.read_byte_at(1).unwrap_err();
+    assert_eq!(
+        err.to_string(),
+        "script: program asked for input while waiting for \"prompt\""
+    );
+}
+
+#[test]
+fn timeouts_start_when_an_expect_becomes_current() {
+    let (mut script, _) = console("timeout 5\nexpect \"a\"\nexpect \"b\"\n");
+    assert_eq!(script.deadline(), Some(5));
+    write(&mut script, 3, b"a");
+    assert_eq!(script.deadline(), Some(8));
+    write(&mut script, 4, b"b");
+    assert_eq!(script.deadline(), None);
+}
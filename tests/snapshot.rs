@@ -0,0 +1,142 @@
+//! Snapshots resume exactly where they were taken, and corrupt ones are
+//! rejected.
+
+use an_urgent_appeal::asm::assemble;
+use an_urgent_appeal::console::BufferConsole;
+use an_urgent_appeal::instructions::ArrayId;
+use an_urgent_appeal::machine::{ExitReason, Machine, StepResult, Word};
+use std::io::{self, ErrorKind};
+
+fn program(source: &str) -> Vec<u8> {
+    assemble(source)
+        .unwrap()
+        .iter()
+        .flat_map(|word| word.to_be_bytes())
+        .collect()
+}
+
+fn arrays(m: &Machine) -> Vec<(ArrayId, Vec<Word>)> {
+    m.data_arrays()
+        .iter()
+        .map(|(id, array)| (id, array.to_vec()))
+        .collect()
+}
+
+#[test]
+fn a_restored_machine_carries_on_where_the_original_stopped() {
+    // Abandons arrays 2 then 1, and after the snapshot allocates them again
+    // in the opposite order.
+    let source = program(
+        "        orthography r1 <- 2
+         \x20       alloc r2 <- r1
+         \x20       alloc r3 <- r1
+         \x20       alloc r4 <- r1
+         \x20       abandon r3
+         \x20       abandon r2
+         \x20       orthography r5 <- 1
+         \x20       amend r4[r5] <- r1
+         \x20       input r6
+         \x20       alloc r2 <- r1
+         \x20       alloc r3 <- r1
+         \x20       input r6
+         \x20       output r6
+         \x20       input r6
+         \x20       output r6
+         \x20       halt\n",
+    );
+    let console = BufferConsole::new(b"xyz");
+    let mut original = Machine::with_console(source, Box::new(console.clone()));
+    assert!(matches!(original.run_for(9), StepResult::Running));
+    let mut snapshot = Vec::new();
+    original.snapshot(&mut snapshot).unwrap();
+
+    let resumed_console = BufferConsole::default();
+    let mut resumed =
+        Machine::restore(&mut &snapshot[..], Box::new(resumed_console.clone())).unwrap();
+    assert_eq!(resumed.finger(), original.finger());
+    assert_eq!(resumed.registers(), original.registers());
+    assert_eq!(resumed.instructions(), 9);
+    assert_eq!(resumed.program(), original.program());
+    assert_eq!(arrays(&resumed), arrays(&original));
+    assert_eq!(arrays(&resumed), [(ArrayId(3), vec![0, 2])]);
+
+    for m in [&mut original, &mut resumed] {
+        assert!(matches!(m.execute(), ExitReason::Halted));
+        assert_eq!(&m.registers()[2..4], [1, 2]);
+    }
+    assert_eq!(resumed.registers(), original.registers());
+    assert_eq!(console.output(), b"yz");
+    assert_eq!(resumed_console.output(), b"yz");
+}
+
+/// A snapshot of a one-`Halt` program with `arrays`, the slot count and
+/// slots, followed by `free`, the free list, and no pending input.
+fn snapshot(arrays: &[Word], free: &[Word]) -> Vec<u8> {
+    let mut words = vec![u32::from_be_bytes(*b"UMSS"), 2, 0];
+    words.extend([0; 8]);
+    words.extend([0, 0, 1, 0x7000_0000]);
+    words.extend(arrays);
+    words.push(free.len() as Word);
+    words.extend(free);
+    words.push(0);
+    words.iter().flat_map(|word| word.to_be_bytes()).collect()
+}
+
+fn restore(bytes: &[u8]) -> io::Result<Machine> {
+    Machine::restore(&mut &bytes[..], Box::new(BufferConsole::default()))
+}
+
+fn rejected(bytes: &[u8]) -> ErrorKind {
+    match restore(bytes) {
+        Ok(_) => panic!("corrupt snapshot restored"),
+        Err(err) => err.kind(),
+    }
+}
+
+#[test]
+fn a_hand_written_snapshot_restores() {
+    let m = restore(&snapshot(&[2, 0, 1, 1, 7], &[1])).unwrap();
+    assert_eq!(arrays(&m), [(ArrayId(2), vec![7])]);
+}
+
+#[test]
+fn snapshots_with_the_wrong_magic_are_rejected() {
+    let mut bytes = snapshot(&[0], &[]);
+    bytes[..4].copy_from_slice(b"UMCD");
+    assert_eq!(rejected(&bytes), ErrorKind::InvalidData);
+}
+
+#[test]
+fn free_lists_must_name_each_abandoned_array_once() {
+    // Out of range, then live, then twice over, then missing one.
+    assert_eq!(rejected(&snapshot(&[1, 0], &[2])), ErrorKind::InvalidData);
+    assert_eq!(
+        rejected(&snapshot(&[2, 0, 1, 0], &[2])),
+        ErrorKind::InvalidData
+    );
+    assert_eq!(
+        rejected(&snapshot(&[2, 0, 0], &[1, 1])),
+        ErrorKind::InvalidData
+    );
+    assert_eq!(
+        rejected(&snapshot(&[2, 0, 0], &[1])),
+        ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn truncated_snapshots_are_rejected() {
+    let bytes = snapshot(&[2, 0, 1, 1, 7], &[1]);
+    restore(&bytes).unwrap();
+    for len in (0..bytes.len()).step_by(4) {
+        assert_eq!(rejected(&bytes[..len]), ErrorKind::UnexpectedEof);
+    }
+}
+
+#[test]
+fn lengths_beyond_the_end_are_not_trusted() {
+    let mut bytes = snapshot(&[0], &[]);
+    let len = bytes.len();
+    bytes[len - 4..].copy_from_slice(&Word::MAX.to_be_bytes());
+    assert_eq!(rejected(&bytes), ErrorKind::UnexpectedEof);
+}
@@ -0,0 +1,26 @@
+//! A machine built on one thread can run on another.
+
+use an_urgent_appeal::asm::assemble;
+use an_urgent_appeal::console::BufferConsole;
+use an_urgent_appeal::machine::{ExitReason, Machine};
+use std::thread;
+
+#[test]
+fn a_machine_runs_on_another_thread() {
+    let program = assemble(
+        "        input r1
+         \x20       output r1
+         \x20       input r1
+         \x20       output r1
+         \x20       halt\n",
+    )
+    .unwrap()
+    .iter()
+    .flat_map(|word| word.to_be_bytes())
+    .collect();
+    let console = BufferConsole::new(b"ok");
+    let mut m = Machine::with_console(program, Box::new(console.clone()));
+    let reason = thread::spawn(move || m.execute()).join().unwrap();
+    assert!(matches!(reason, ExitReason::Halted));
+    assert_eq!(console.output(), b"ok");
+}